@@ -0,0 +1,50 @@
+//! Command-line arguments for the admin tool.
+//!
+//! This module defines the CLI interface using `clap`. See `main` for end-to-end usage.
+use clap::{Parser, Subcommand};
+use quote_common::net::ADMIN_PORT;
+use quote_common::tickers::Ticker;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+/// Parsed command-line arguments.
+#[derive(Debug, Parser)]
+#[command(version, about, long_about = None)]
+pub struct Args {
+    /// Address of the server's admin listener.
+    #[clap(long, default_value_t = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), ADMIN_PORT))]
+    pub addr: SocketAddr,
+
+    /// Print the server's reply as JSON instead of a table.
+    #[clap(long)]
+    pub json: bool,
+
+    /// What to ask the server.
+    #[command(subcommand)]
+    pub command: AdminCommand,
+}
+
+/// Operator commands, one per admin request.
+#[derive(Debug, Subcommand)]
+pub enum AdminCommand {
+    /// List connected clients with their tickers, age and last ping.
+    Clients,
+    /// End a client's stream.
+    Kick {
+        /// Client address as shown by `clients`, e.g. `127.0.0.1:55555`.
+        client: SocketAddr,
+    },
+    /// Stop generating quotes for a ticker.
+    Pause {
+        /// Ticker to pause.
+        #[arg(ignore_case = true, hide_possible_values = true)]
+        ticker: Ticker,
+    },
+    /// Generate quotes for a paused ticker again.
+    Resume {
+        /// Ticker to resume.
+        #[arg(ignore_case = true, hide_possible_values = true)]
+        ticker: Ticker,
+    },
+    /// Print generator statistics.
+    Stats,
+}
@@ -0,0 +1,141 @@
+//! Quote Admin — a small operator tool for a running quote server.
+//!
+//! It connects to the server's admin port (localhost `8082` by default), sends a single
+//! `AdminRequest` and prints the reply as a table, or as JSON with `--json`.
+//!
+//! Usage example (CLI):
+//! ```bash
+//! quote_admin clients
+//! quote_admin kick 127.0.0.1:55555
+//! quote_admin pause TSLA
+//! quote_admin resume TSLA
+//! quote_admin --addr 127.0.0.1:9082 stats
+//! ```
+//!
+//! Exit codes:
+//! - `0` — the server applied the request.
+//! - `1` — the server refused the request, or any other error.
+//! - `2` — the admin port could not be reached or did not answer in time.
+#![warn(missing_docs)]
+mod args;
+
+use crate::args::{AdminCommand, Args};
+use clap::Parser;
+use quote_common::ParserError;
+use quote_common::admin::{AdminRequest, AdminResponse, ClientInfo, GeneratorStats};
+use quote_common::framing::{read_message, write_message};
+use std::net::{SocketAddr, TcpStream};
+use std::process::ExitCode;
+use std::time::Duration;
+
+/// How long to wait for the connection and for the reply.
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// Exit code for generic failures.
+const EXIT_FAILURE: u8 = 1;
+/// Exit code when the admin port is unreachable or silent.
+const EXIT_SERVER_UNAVAILABLE: u8 = 2;
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            match e {
+                ParserError::ServerUnavailable(_) => ExitCode::from(EXIT_SERVER_UNAVAILABLE),
+                _ => ExitCode::from(EXIT_FAILURE),
+            }
+        }
+    }
+}
+
+fn run(args: Args) -> Result<(), ParserError> {
+    let request = match args.command {
+        AdminCommand::Clients => AdminRequest::ListClients,
+        AdminCommand::Kick { client } => AdminRequest::Kick { client },
+        AdminCommand::Pause { ticker } => AdminRequest::PauseTicker { ticker },
+        AdminCommand::Resume { ticker } => AdminRequest::ResumeTicker { ticker },
+        AdminCommand::Stats => AdminRequest::Stats,
+    };
+    let response = send(args.addr, &request)?;
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&response)?);
+    }
+
+    match response {
+        AdminResponse::Clients { clients } if !args.json => print_clients(&clients),
+        AdminResponse::Stats { stats } if !args.json => print_stats(&stats),
+        AdminResponse::Done { message } if !args.json => println!("{}", message),
+        AdminResponse::Error { message } => return Err(ParserError::AdminRefused(message)),
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Send `request` to the admin listener at `addr` and wait for the reply.
+fn send(addr: SocketAddr, request: &AdminRequest) -> Result<AdminResponse, ParserError> {
+    let unavailable = |e: std::io::Error| ParserError::ServerUnavailable(format!("{}: {}", addr, e));
+    let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT).map_err(unavailable)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    write_message(&mut stream, request)?;
+    match read_message::<_, AdminResponse>(&mut stream) {
+        Ok(Some(response)) => Ok(response),
+        Ok(None) => Err(ParserError::ServerUnavailable(format!("{} closed the connection", addr))),
+        Err(ParserError::Io(e)) => Err(unavailable(e)),
+        Err(e) => Err(e),
+    }
+}
+
+fn print_clients(clients: &[ClientInfo]) {
+    if clients.is_empty() {
+        println!("No clients connected");
+        return;
+    }
+    println!(
+        "{:<6} {:<24} {:<5} {:>9} {:>10} {:>8}  TICKERS",
+        "ID", "ADDRESS", "VIA", "AGE", "LAST PING", "DROPPED"
+    );
+    for client in clients {
+        let tickers: Vec<String> = client.tickers.iter().map(|t| t.to_string()).collect();
+        let mut tickers = tickers.join(",");
+        if !client.depth.is_empty() {
+            let depth: Vec<String> = client.depth.iter().map(|t| t.to_string()).collect();
+            tickers = format!("{} (depth: {})", tickers, depth.join(","));
+        }
+        println!(
+            "{:<6} {:<24} {:<5} {:>9} {:>10} {:>8}  {}",
+            client.subscription_id,
+            client.address,
+            client.transport,
+            format_ms(client.age_ms),
+            client.last_ping_ms.map_or_else(|| "-".to_string(), format_ms),
+            client.dropped_quotes,
+            tickers
+        );
+    }
+}
+
+fn print_stats(stats: &GeneratorStats) {
+    println!("Uptime:        {}", format_ms(stats.uptime_ms));
+    println!("Tick interval: {} ms", stats.tick_interval_ms);
+    println!("Ticks:         {}", stats.ticks);
+    println!("Quotes:        {}", stats.quotes);
+    println!("Subscribers:   {}", stats.subscribers);
+    println!();
+    println!("{:<8} {:>12} {:>10} {:>11}  STATE", "TICKER", "MID", "SEQ", "SUBSCRIBERS");
+    for ticker in &stats.tickers {
+        println!(
+            "{:<8} {:>12.4} {:>10} {:>11}  {}",
+            ticker.ticker.to_string(),
+            ticker.price,
+            ticker.seq,
+            ticker.subscribers,
+            if ticker.paused { "paused" } else { "live" }
+        );
+    }
+}
+
+/// Render a duration in milliseconds as seconds with one decimal.
+fn format_ms(ms: u64) -> String {
+    format!("{:.1}s", ms as f64 / 1000.0)
+}
@@ -2,6 +2,10 @@
 //!
 //! This module defines the CLI interface using `clap`. See `main` for end-to-end usage.
 use clap::Parser;
+use quote_common::command::Transport;
+use quote_common::handshake::Encoding;
+use quote_common::net::{COMMAND_PORT, DATA_PORT};
+use quote_common::tickers::Ticker;
 
 /// Parsed command-line arguments.
 #[derive(Debug, Parser)]
@@ -11,12 +15,58 @@ pub struct Args {
     #[clap(long)]
     pub server_ip: String,
 
+    /// TCP command port of the server.
+    #[clap(long, default_value_t = COMMAND_PORT)]
+    pub command_port: u16,
+
+    /// UDP data port of the server, which receives PINGs.
+    #[clap(long, default_value_t = DATA_PORT)]
+    pub data_port: u16,
+
     /// Local UDP port to bind for receiving quotes and sending commands.
+    /// Required with the UDP transport; unused with TCP.
     #[clap(long)]
-    pub listen_port: String,
+    pub listen_port: Option<String>,
 
     /// Path to a text file with tickers to subscribe to.
     /// Tickers may be separated by commas, spaces, or new lines.
     #[clap(long)]
-    pub path: String
+    pub path: String,
+
+    /// Tickers whose order book to follow as well, separated by commas.
+    #[clap(long, value_delimiter = ',', ignore_case = true)]
+    pub depth: Vec<Ticker>,
+
+    /// How quotes are delivered: UDP datagrams, or frames on the TCP command connection
+    /// (for networks that drop inbound UDP).
+    #[clap(long, value_enum, default_value_t = Transport::Udp)]
+    pub transport: Transport,
+
+    /// Encoding of quote datagrams requested from the server.
+    #[clap(long, value_enum, default_value_t = Encoding::Json)]
+    pub encoding: Encoding,
+
+    /// Longest time the server may hold a quote to batch it with later ones, in milliseconds.
+    #[clap(long, default_value_t = 5)]
+    pub max_batch_delay_ms: u64,
+
+    /// Receive only the latest quote of each ticker once per this many milliseconds
+    /// (0 receives every tick). Sequence gaps are expected and not filled.
+    #[clap(long, default_value_t = 0)]
+    pub conflate_ms: u64,
+
+    /// Receive at most this many updates per second per ticker (0 is unlimited).
+    /// Sequence gaps are expected and not filled.
+    #[clap(long, default_value_t = 0)]
+    pub max_updates_per_sec: u32,
+
+    /// How often to PING the server, in milliseconds; the server may adjust it.
+    /// Defaults to the server's choice.
+    #[clap(long)]
+    pub ping_interval_ms: Option<u64>,
+
+    /// Silence after which the server should drop the subscription, in milliseconds.
+    /// Defaults to the server's setting.
+    #[clap(long)]
+    pub ping_timeout_ms: Option<u64>,
 }
\ No newline at end of file
@@ -1,26 +1,46 @@
 //! Quote Client — a UDP client that subscribes to stock quotes from a server and prints
 //! received quotes to stdout. It reads a list of tickers from a text file, sends an
 //! initial `J_QUOTE` subscription command to the server, keeps the connection alive
-//! with periodic `PING`s, and continuously listens for incoming quotes.
+//! with periodic `PING`s, and continuously listens for incoming quotes. While running,
+//! `add`/`remove` lines on stdin change the live subscription, quotes lost on the way are
+//! requested again with `RETRANSMIT`, and the client sends `UNSUBSCRIBE` before exiting.
+//! With `--depth` (or `depth`/`nodepth` on stdin) the client also follows the order books
+//! of the given tickers, rebuilding each one from the server's snapshots and updates and
+//! printing it as a price ladder.
 //!
 //! Usage example (CLI):
 //! ```bash
 //! quote_client --server-ip 192.168.0.10 --listen-port 55555 --path ./tickers.txt
+//! quote_client --server-ip 192.168.0.10 --listen-port 55555 --path ./tickers.txt --encoding binary
+//! quote_client --server-ip 192.168.0.10 --path ./tickers.txt --transport tcp
+//! quote_client --server-ip 192.168.0.10 --listen-port 55555 --path ./tickers.txt --depth AAPL,MSFT
 //! ```
 //!
+//! With `--transport tcp` quotes are streamed over the command connection instead of UDP,
+//! for networks that drop inbound datagrams.
+//!
 //! The ticker file should contain symbols separated by commas, spaces, or new lines.
 //! See `model::tickers` for details.
+//!
+//! Exit codes:
+//! - `0` — the subscription succeeded and the client was stopped with Ctrl+C.
+//! - `1` — any other error (bad arguments, I/O failure, malformed data).
+//! - `2` — the server could not be reached, did not answer in time or shut down.
+//! - `3` — the server rejected one or more of the requested tickers.
 #![warn(missing_docs)]
 mod args;
 mod model;
 mod sender;
 
 use crate::args::Args;
-use quote_common::quote::Quote;
-use crate::sender::CommandSender;
+use crate::model::book::{BookEvent, BookTracker};
+use crate::model::sequence::{SequenceEvent, SequenceTracker};
+use quote_common::book::BookMessage;
+use quote_common::quote::{Quote, QuoteCodec, StreamNotice};
+use crate::sender::{CommandChannel, CommandSender, GapFill};
 use clap::Parser;
 use log::{debug, error, info, warn};
-use quote_common::command::Command;
+use quote_common::command::{Endpoint, Request, Transport};
 use quote_common::tickers::Ticker;
 use quote_common::tickers::TickerParser;
 use quote_common::ParserError;
@@ -28,53 +48,220 @@ use quote_common::Result;
 use std::fs::File;
 use std::io::BufReader;
 use std::io::ErrorKind;
-use std::net::{TcpStream, UdpSocket};
-use std::path::PathBuf;
+use std::net::UdpSocket;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
 use std::sync::{
     atomic::{AtomicBool, Ordering},
-    Arc,
+    Arc, Mutex,
 };
 use std::time::Duration;
 
-/// UDP port on which the quote server is expected to listen.
-use quote_common::net::{COMMAND_PORT, DATA_PORT};
+use quote_common::net::addr;
 
+/// How long the receiver waits for quotes before re-checking the shutdown flag.
+const RECEIVE_TIMEOUT: Duration = Duration::from_secs(5);
 
-/// Runs a blocking loop that receives `Quote` messages from the given UDP `socket`
-/// and prints them to stdout. Returns an error if receiving or decoding fails.
-fn start_receiver_loop(socket: Arc<UdpSocket>, shutdown: Arc<AtomicBool>) -> Result<(), ParserError> {
-    info!("Quote receiver running on: {}", socket.local_addr()?);
+/// Where the client receives quotes from.
+enum QuoteSource {
+    /// Datagrams on the client's UDP socket.
+    Udp(Arc<UdpSocket>),
+    /// Frames demultiplexed from the TCP command connection.
+    Tcp(Receiver<Vec<u8>>),
+}
+
+/// Runs a blocking loop that receives `Quote` messages from `source`, decodes them
+/// (single quotes or batches) with `codec` and prints them to stdout.
+/// Returns an error if receiving fails, the server closes a TCP stream or the server sends
+/// a "going away" notice.
+///
+/// When the session negotiated sequence numbers, every quote is passed through `tracker`
+/// and gaps, duplicates and reordered quotes are logged. Detected gaps are forwarded to
+/// `gap_tx` so the missing quotes can be requested again.
+///
+/// Order book messages rebuild the client's copy of the books, which is printed after
+/// every change.
+fn start_receiver_loop(
+    source: QuoteSource,
+    codec: &dyn QuoteCodec,
+    tracker: Option<Arc<Mutex<SequenceTracker>>>,
+    gap_tx: Option<Sender<GapFill>>,
+    shutdown: Arc<AtomicBool>,
+) -> Result<(), ParserError> {
     let mut buf = [0u8; 2048];
+    let mut going_away = None;
+    let mut books = BookTracker::new();
+    // Returns `false` once the server announced that it is going away.
+    let mut handle = |payload: &[u8]| -> Result<bool, ParserError> {
+        if let Some(StreamNotice::GoingAway { reason }) = StreamNotice::from_slice(payload) {
+            warn!("Server is going away: {}", reason);
+            going_away = Some(reason);
+            return Ok(false);
+        }
+        if let Some(message) = BookMessage::from_slice(payload) {
+            print_book(&message, &mut books);
+            return Ok(true);
+        }
+        match codec.decode_batch(payload) {
+            Ok(quotes) => {
+                for quote in quotes {
+                    print_quote(&quote, tracker.as_deref(), gap_tx.as_ref())?;
+                }
+            }
+            Err(_) => {
+                debug!(
+                    "Received non-{} message: {}",
+                    codec.encoding(),
+                    String::from_utf8_lossy(payload)
+                );
+            }
+        }
+        Ok(true)
+    };
 
-    while !shutdown.load(Ordering::Relaxed) {
-        match socket.recv(&mut buf) {
-            Ok(size) => {
-                match serde_json::from_slice::<Quote>(&buf[..size]) {
-                    Ok(quote) => {
-                        info!("QUOTE: {} Price={:.2} Volume={} Time={}",
-                            quote.ticker, quote.price, quote.volume, quote.timestamp);
+    match source {
+        QuoteSource::Udp(socket) => {
+            info!("Quote receiver running on: {}", socket.local_addr()?);
+            while !shutdown.load(Ordering::Relaxed) {
+                match socket.recv(&mut buf) {
+                    Ok(size) => {
+                        if !handle(&buf[..size])? {
+                            break;
+                        }
                     }
-                    Err(_) => {
-                        debug!("Received non-JSON message: {}", String::from_utf8_lossy(&buf[..size]));
+                    Err(e) => {
+                        if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted) {
+                            continue;
+                        }
+                        error!("Receive data error: {}", e);
+                        return Err(ParserError::Format(e.to_string()));
                     }
                 }
             }
-            Err(e) => {
-                if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut {
-                    continue;
+        }
+        QuoteSource::Tcp(frames) => {
+            info!("Quote receiver reading the TCP command connection");
+            while !shutdown.load(Ordering::Relaxed) {
+                match frames.recv_timeout(RECEIVE_TIMEOUT) {
+                    Ok(payload) => {
+                        if !handle(&payload)? {
+                            break;
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => {
+                        return Err(ParserError::ServerUnavailable("server closed the quote stream".to_string()));
+                    }
                 }
-                error!("Receive data error: {}", e);
-                return Err(ParserError::Format(e.to_string()));
             }
         }
     }
+    if let Some(reason) = going_away {
+        return Err(ParserError::ServerUnavailable(format!("server ended the stream: {}", reason)));
+    }
     info!("Receiver loop stopping...");
     Ok(())
 }
 
-fn main() -> Result<(), ParserError> {
+/// Print a received quote and pass it through the sequence `tracker`, if any.
+///
+/// Gaps are reported on `gap_tx` for retransmission.
+fn print_quote(
+    quote: &Quote,
+    tracker: Option<&Mutex<SequenceTracker>>,
+    gap_tx: Option<&Sender<GapFill>>,
+) -> Result<(), ParserError> {
+    info!("QUOTE: {}", format_quote(quote));
+    if let Some(tracker) = tracker {
+        let mut tracker = tracker.lock()?;
+        match tracker.observe(&quote.ticker, quote.seq) {
+            SequenceEvent::InOrder => {}
+            SequenceEvent::Gap { from, to } => {
+                warn!(
+                    "GAP: {} missing seq {}..={} ({})",
+                    quote.ticker, from, to, tracker.stats()
+                );
+                if let Some(gap_tx) = gap_tx {
+                    let gap = GapFill { ticker: quote.ticker.clone(), from, to };
+                    if gap_tx.send(gap).is_err() {
+                        debug!("Gap-fill thread stopped; not requesting {} {}..={}", quote.ticker, from, to);
+                    }
+                }
+            }
+            SequenceEvent::OutOfOrder => warn!(
+                "OUT OF ORDER: {} seq {} ({})",
+                quote.ticker, quote.seq, tracker.stats()
+            ),
+            SequenceEvent::Duplicate => warn!(
+                "DUPLICATE: {} seq {} ({})",
+                quote.ticker, quote.seq, tracker.stats()
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Describe `quote` for the log, with its top of book when the server sent one.
+fn format_quote(quote: &Quote) -> String {
+    if quote.has_top_of_book() {
+        format!("{} Bid={:.2}x{} Ask={:.2}x{} Last={:.2}x{} Time={} Seq={}",
+            quote.ticker, quote.bid, quote.bid_size, quote.ask, quote.ask_size,
+            quote.price, quote.volume, quote.timestamp, quote.seq)
+    } else {
+        format!("{} Price={:.2} Volume={} Time={} Seq={}",
+            quote.ticker, quote.price, quote.volume, quote.timestamp, quote.seq)
+    }
+}
+
+/// Apply a received book message to `books` and print the resulting book.
+fn print_book(message: &BookMessage, books: &mut BookTracker) {
+    match books.apply(message) {
+        BookEvent::Updated => {
+            if let Some(book) = books.render(message.ticker()) {
+                info!("{}", book);
+            }
+        }
+        BookEvent::Gap { expected, received } => warn!(
+            "BOOK GAP: {} expected seq {}, got {}; waiting for the next snapshot",
+            message.ticker(), expected, received
+        ),
+        BookEvent::Skipped => debug!("Skipped book message {} seq {}", message.ticker(), message.seq()),
+    }
+}
+
+/// Exit code for errors not covered by a more specific code.
+const EXIT_FAILURE: u8 = 1;
+/// Exit code when the server is unreachable or does not respond.
+const EXIT_SERVER_UNAVAILABLE: u8 = 2;
+/// Exit code when the server rejects the requested tickers.
+const EXIT_SUBSCRIPTION_REJECTED: u8 = 3;
+
+fn main() -> ExitCode {
     init_logger();
     let args = Args::parse();
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            error!("{}", e);
+            ExitCode::from(exit_code(&e))
+        }
+    }
+}
+
+/// Map an error to the process exit code reported to calling scripts.
+fn exit_code(err: &ParserError) -> u8 {
+    match err {
+        ParserError::ServerUnavailable(_) => EXIT_SERVER_UNAVAILABLE,
+        ParserError::SubscriptionRejected(_) => EXIT_SUBSCRIPTION_REJECTED,
+        _ => EXIT_FAILURE,
+    }
+}
+
+fn run(args: Args) -> Result<(), ParserError> {
+    if args.transport == Transport::Udp && args.listen_port.is_none() {
+        return Err(ParserError::Format("--listen-port is required with the UDP transport".to_string()));
+    }
     let shutdown = Arc::new(AtomicBool::new(false));
     {
         let shutdown = shutdown.clone();
@@ -86,18 +273,9 @@ fn main() -> Result<(), ParserError> {
     }
 
     let server_ip = args.server_ip.trim().replace("\"", "").to_string();
-    let listen_port = args.listen_port.trim().replace("\"", "").to_string();
-
-    let server_command_address = format!("{}:{}", server_ip, COMMAND_PORT);
-    let server_udp_address = format!("{}:{}", server_ip, DATA_PORT);
-    let mut listen_address = format!("0.0.0.0:{}", listen_port);
-    if listen_port == DATA_PORT.to_string() {
-        warn!(
-            "--listen-port={} matches the server port DATA_PORT ({}). A free local port will be selected.",
-            listen_port, DATA_PORT
-        );
-        listen_address = "0.0.0.0:0".to_string();
-    }
+
+    let server_command_address = addr(&server_ip, args.command_port);
+    let server_udp_address = addr(&server_ip, args.data_port);
 
     let file_path = normalize_path(&args.path);
 
@@ -109,51 +287,136 @@ fn main() -> Result<(), ParserError> {
 
         let tickers = Ticker::parse_from_file(buf)?;
         info!("Tickers: {:?}", tickers);
-        let client_udp_socket = Arc::new(UdpSocket::bind(&listen_address)?);
-        client_udp_socket.set_read_timeout(Some(Duration::from_secs(5)))?;
-        let client_local_addr = client_udp_socket.local_addr()?;
-
-        info!("UDP client listening on: {}", client_local_addr);
 
         info!("Connecting to TCP server at {}", server_command_address);
-        let mut tcp_stream = TcpStream::connect(&server_command_address)
-            .map_err(|e| ParserError::Format(format!("Failed to connect to server: {}", e)))?;
+        let mut channel = CommandChannel::connect(&server_command_address)?;
 
-        let command = Command::new(
-            &client_local_addr.ip().to_string(),
-            &client_local_addr.port().to_string(),
-            tickers.clone(),
+        let session = CommandSender::handshake(&mut channel)?;
+        info!(
+            "Negotiated protocol v{} with {} encoding: {:?}",
+            session.version,
+            session.encoding(),
+            session.capabilities
         );
 
+        let (endpoint, source) = match args.transport {
+            Transport::Udp => {
+                let listen_port = args.listen_port.unwrap_or_default().trim().replace("\"", "");
+                let mut listen_address = format!("0.0.0.0:{}", listen_port);
+                if listen_port == args.data_port.to_string() {
+                    warn!(
+                        "--listen-port={} matches the server data port ({}). A free local port will be selected.",
+                        listen_port, args.data_port
+                    );
+                    listen_address = "0.0.0.0:0".to_string();
+                }
+                let client_udp_socket = Arc::new(UdpSocket::bind(&listen_address)?);
+                client_udp_socket.set_read_timeout(Some(RECEIVE_TIMEOUT))?;
+                let client_local_addr = client_udp_socket.local_addr()?;
+                info!("UDP client listening on: {}", client_local_addr);
+
+                let endpoint = Endpoint::new(Transport::Udp, client_local_addr.ip(), client_local_addr.port());
+                (endpoint, QuoteSource::Udp(client_udp_socket))
+            }
+            Transport::Tcp => {
+                // Quotes may arrive before the subscription response, so start splitting
+                // frames before subscribing.
+                let local_addr = channel.local_addr()?;
+                let frames = channel.split_quotes()?;
+                info!("Quotes will be streamed over the command connection {}", local_addr);
+                (Endpoint::new(Transport::Tcp, local_addr.ip(), local_addr.port()), QuoteSource::Tcp(frames))
+            }
+        };
+        let command = Request::Subscribe {
+            endpoint,
+            tickers: tickers.clone(),
+            depth: args.depth.clone(),
+            encoding: args.encoding,
+            max_batch_delay_ms: args.max_batch_delay_ms,
+            conflate_ms: args.conflate_ms,
+            max_updates_per_sec: args.max_updates_per_sec,
+            ping_interval_ms: args.ping_interval_ms,
+            ping_timeout_ms: args.ping_timeout_ms,
+        };
+
         info!(
             "Preparing to send J_QUOTE to TCP server {}",
             server_command_address
         );
 
-        match CommandSender::send_command(&mut tcp_stream, &command) {
-            Ok(_) => {
-                info!("Initial command sent to server {}.", server_command_address);
-            }
-            Err(e) => {
-                error!("Sending error to server: {}", e.to_string());
-                return Err(ParserError::Format(e.to_string()));
+        let response = CommandSender::send_command(&mut channel, &command)?;
+        info!(
+            "Subscription #{} accepted by server {} (version {}): {:?}",
+            response.subscription_id.unwrap_or_default(),
+            server_command_address,
+            response.server_version,
+            response.accepted
+        );
+        if !response.depth.is_empty() {
+            info!("Following the order books of {:?}", response.depth);
+        }
+
+        // A TCP stream lives as long as the connection and needs no keep-alive.
+        if let QuoteSource::Udp(client_udp_socket) = &source {
+            if let Some(terms) = response.ping {
+                info!(
+                    "Agreed to ping every {} ms; the server drops the stream after {} ms of silence",
+                    terms.interval_ms, terms.timeout_ms
+                );
             }
-        };
 
-        let ping_command = Command::new_ping(
-            &client_local_addr.ip().to_string(),
-            &client_local_addr.port().to_string(),
-        );
+            CommandSender::start_ping_thread(
+                client_udp_socket.clone(),
+                server_udp_address.clone(),
+                response.ping.map(|terms| Duration::from_millis(terms.interval_ms)),
+                shutdown.clone(),
+            );
+        }
+
+        // Conflation skips sequence numbers on purpose, so gaps say nothing about loss.
+        let conflated = Request::conflation_interval(args.conflate_ms, args.max_updates_per_sec);
+        if let Some(interval) = conflated {
+            info!("Quotes are conflated every {:?}; sequence tracking is off", interval);
+        }
+        let tracker = (session.capabilities.sequence_numbers && conflated.is_none())
+            .then(|| Arc::new(Mutex::new(SequenceTracker::new())));
 
-        CommandSender::start_ping_thread(
-            client_udp_socket.clone(),
-            server_udp_address.clone(),
-            ping_command,
+        let channel = Arc::new(Mutex::new(channel));
+        let unsubscribed = Arc::new(AtomicBool::new(false));
+        CommandSender::start_console_thread(
+            channel.clone(),
+            endpoint,
+            tracker.clone(),
+            unsubscribed.clone(),
             shutdown.clone(),
         );
 
-        info!("Client is running. Press Ctrl+C to exit.");
-        return start_receiver_loop(client_udp_socket, shutdown);
+        let gap_tx = tracker.clone().map(|tracker| {
+            let (gap_tx, gap_rx) = mpsc::channel();
+            CommandSender::start_gap_fill_thread(
+                channel.clone(),
+                endpoint,
+                tracker,
+                gap_rx,
+                |quote: &Quote| info!("RECOVERED: {}", format_quote(quote)),
+            );
+            gap_tx
+        });
+
+        info!("Client is running. Type `add`, `remove`, `depth`, `nodepth` or `unsubscribe`, or press Ctrl+C to exit.");
+        let result = start_receiver_loop(source, args.encoding.codec(session.capabilities.top_of_book), tracker.clone(), gap_tx, shutdown);
+        if let Some(tracker) = &tracker {
+            info!("Sequence summary: {}", tracker.lock()?.stats());
+        }
+        result?;
+
+        // The console may already have unsubscribed.
+        if !unsubscribed.load(Ordering::SeqCst) {
+            let unsubscribe = Request::Unsubscribe { endpoint };
+            let mut channel = channel.lock()?;
+            CommandSender::send_command(&mut channel, &unsubscribe)?;
+        }
+        info!("Unsubscribed from server {}.", server_command_address);
     }
 
     Ok(())
@@ -179,6 +442,6 @@ fn normalize_path(raw: &str) -> PathBuf {
 }
 
 /// Returns `true` if the provided path exists and is a regular file.
-fn is_file_exist(path: &PathBuf) -> bool {
+fn is_file_exist(path: &Path) -> bool {
     path.exists() && path.is_file()
 }
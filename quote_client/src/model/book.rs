@@ -0,0 +1,217 @@
+//! Client-side reconstruction of Level 2 order books.
+//!
+//! `BookTracker` keeps one book per ticker and applies the server's book messages to it:
+//!
+//! - a snapshot replaces the book, unless an update newer than the snapshot was already
+//!   applied;
+//! - an update is applied when its sequence number is the next one. Any other number means
+//!   that an update was lost, so the book is marked stale and updates are ignored until the
+//!   next snapshot.
+//!
+//! Updates that arrive before the first snapshot of a ticker are ignored as well.
+
+use quote_common::book::{BookLevel, BookMessage, Side};
+use quote_common::quote::{from_ticks, to_ticks};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+/// Outcome of applying a book message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookEvent {
+    /// The book changed and is consistent with the server's.
+    Updated,
+    /// An update was lost; the book is stale until the next snapshot.
+    Gap {
+        /// Sequence number the book expected.
+        expected: u64,
+        /// Sequence number that arrived.
+        received: u64,
+    },
+    /// The message was ignored: the book is stale, has no snapshot yet, or the snapshot is
+    /// older than the book.
+    Skipped,
+}
+
+/// Order book of one ticker, prices in ticks.
+#[derive(Debug, Default)]
+struct Book {
+    /// Bid sizes by price; the best bid is the last entry.
+    bids: BTreeMap<u64, u32>,
+    /// Ask sizes by price; the best ask is the first entry.
+    asks: BTreeMap<u64, u32>,
+    /// Sequence number of the last applied message.
+    seq: u64,
+    /// An update was lost since the last snapshot.
+    stale: bool,
+}
+
+impl Book {
+    fn side(&mut self, side: Side) -> &mut BTreeMap<u64, u32> {
+        match side {
+            Side::Bid => &mut self.bids,
+            Side::Ask => &mut self.asks,
+        }
+    }
+}
+
+/// Books of every ticker the client follows the depth of.
+#[derive(Debug, Default)]
+pub struct BookTracker {
+    books: HashMap<String, Book>,
+}
+
+impl BookTracker {
+    /// Create a tracker with no books.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply `message` to the book of its ticker.
+    pub fn apply(&mut self, message: &BookMessage) -> BookEvent {
+        match message {
+            BookMessage::BookSnapshot { ticker, seq, bids, asks, .. } => {
+                if let Some(book) = self.books.get(ticker)
+                    && !book.stale
+                    && *seq < book.seq
+                {
+                    return BookEvent::Skipped;
+                }
+                let levels = |side: &[BookLevel]| side.iter().map(|level| (to_ticks(level.price), level.size)).collect();
+                self.books.insert(
+                    ticker.clone(),
+                    Book { bids: levels(bids), asks: levels(asks), seq: *seq, stale: false },
+                );
+                BookEvent::Updated
+            }
+            BookMessage::BookUpdate { ticker, seq, changes, .. } => {
+                let Some(book) = self.books.get_mut(ticker) else {
+                    return BookEvent::Skipped;
+                };
+                if book.stale {
+                    return BookEvent::Skipped;
+                }
+                if *seq != book.seq + 1 {
+                    book.stale = true;
+                    return BookEvent::Gap { expected: book.seq + 1, received: *seq };
+                }
+                for change in changes {
+                    let side = book.side(change.side);
+                    match change.size {
+                        0 => side.remove(&to_ticks(change.price)),
+                        size => side.insert(to_ticks(change.price), size),
+                    };
+                }
+                book.seq = *seq;
+                BookEvent::Updated
+            }
+        }
+    }
+
+    /// Render the book of `ticker` as a price ladder, best levels on the first row.
+    pub fn render(&self, ticker: &str) -> Option<String> {
+        let book = self.books.get(ticker)?;
+        let mut bids = book.bids.iter().rev();
+        let mut asks = book.asks.iter();
+
+        let mut out = format!("BOOK: {} Seq={}{}", ticker, book.seq, if book.stale { " (stale)" } else { "" });
+        let _ = write!(out, "\n  {:>8} {:>10} | {:<10} {:<8}", "BID SIZE", "BID", "ASK", "ASK SIZE");
+        loop {
+            let (bid, ask) = (bids.next(), asks.next());
+            if bid.is_none() && ask.is_none() {
+                break;
+            }
+            let (bid_size, bid_price) = match bid {
+                Some((&price, &size)) => (size.to_string(), format!("{:.2}", from_ticks(price))),
+                None => (String::new(), String::new()),
+            };
+            let (ask_price, ask_size) = match ask {
+                Some((&price, &size)) => (format!("{:.2}", from_ticks(price)), size.to_string()),
+                None => (String::new(), String::new()),
+            };
+            let _ = write!(out, "\n  {:>8} {:>10} | {:<10} {:<8}", bid_size, bid_price, ask_price, ask_size);
+        }
+        Some(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quote_common::book::LevelChange;
+
+    fn snapshot(seq: u64) -> BookMessage {
+        BookMessage::BookSnapshot {
+            ticker: "AAPL".to_string(),
+            seq,
+            timestamp: 0,
+            bids: vec![BookLevel { price: 190.00, size: 100 }, BookLevel { price: 189.99, size: 200 }],
+            asks: vec![BookLevel { price: 190.02, size: 300 }],
+        }
+    }
+
+    fn update(seq: u64, changes: Vec<LevelChange>) -> BookMessage {
+        BookMessage::BookUpdate { ticker: "AAPL".to_string(), seq, timestamp: 0, changes }
+    }
+
+    fn change(side: Side, price: f64, size: u32) -> LevelChange {
+        LevelChange { side, price, size }
+    }
+
+    /// Rows of the rendered ladder below its two header lines.
+    fn rows(tracker: &BookTracker) -> Vec<String> {
+        let book = tracker.render("AAPL").expect("book exists");
+        book.lines().skip(2).map(|row| row.split_whitespace().collect::<Vec<_>>().join(" ")).collect()
+    }
+
+    #[test]
+    fn a_snapshot_replaces_the_book() {
+        let mut tracker = BookTracker::new();
+        assert_eq!(tracker.apply(&snapshot(7)), BookEvent::Updated);
+        assert_eq!(rows(&tracker), ["100 190.00 | 190.02 300", "200 189.99 |"]);
+        assert!(tracker.render("AAPL").unwrap().starts_with("BOOK: AAPL Seq=7\n"));
+    }
+
+    #[test]
+    fn updates_resize_add_and_remove_levels() {
+        let mut tracker = BookTracker::new();
+        tracker.apply(&snapshot(7));
+        let changes = vec![
+            change(Side::Bid, 190.00, 0),
+            change(Side::Bid, 189.99, 250),
+            change(Side::Ask, 190.03, 400),
+        ];
+        assert_eq!(tracker.apply(&update(8, changes)), BookEvent::Updated);
+        assert_eq!(rows(&tracker), ["250 189.99 | 190.02 300", "| 190.03 400"]);
+    }
+
+    #[test]
+    fn updates_before_the_first_snapshot_are_skipped() {
+        let mut tracker = BookTracker::new();
+        assert_eq!(tracker.apply(&update(1, vec![change(Side::Bid, 1.0, 1)])), BookEvent::Skipped);
+        assert!(tracker.render("AAPL").is_none());
+    }
+
+    #[test]
+    fn a_sequence_gap_waits_for_the_next_snapshot() {
+        let mut tracker = BookTracker::new();
+        tracker.apply(&snapshot(7));
+        let lost = update(10, vec![change(Side::Ask, 190.02, 0)]);
+        assert_eq!(tracker.apply(&lost), BookEvent::Gap { expected: 8, received: 10 });
+        assert!(tracker.render("AAPL").unwrap().contains("(stale)"));
+
+        // Stale until a snapshot arrives, even for the number that would have been next.
+        assert_eq!(tracker.apply(&update(8, Vec::new())), BookEvent::Skipped);
+        assert_eq!(tracker.apply(&snapshot(10)), BookEvent::Updated);
+        assert!(!tracker.render("AAPL").unwrap().contains("(stale)"));
+        assert_eq!(tracker.apply(&update(11, Vec::new())), BookEvent::Updated);
+    }
+
+    #[test]
+    fn snapshots_older_than_the_book_are_skipped() {
+        let mut tracker = BookTracker::new();
+        tracker.apply(&snapshot(7));
+        tracker.apply(&update(8, vec![change(Side::Bid, 190.00, 500)]));
+        assert_eq!(tracker.apply(&snapshot(7)), BookEvent::Skipped);
+        assert_eq!(rows(&tracker)[0], "500 190.00 | 190.02 300");
+    }
+}
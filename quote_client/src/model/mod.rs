@@ -3,4 +3,9 @@
 //! This module groups simple serializable types used by the client:
 //! - `quote` — market quote payloads received from the server.
 //! - `tickers` — ticker symbols and parsing helpers.
+//! - `sequence` — per-ticker sequence tracking for gap and duplicate detection.
+//! - `book` — Level 2 order books rebuilt from the server's depth messages.
+
+pub mod sequence;
+pub mod book;
 
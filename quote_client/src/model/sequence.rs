@@ -0,0 +1,254 @@
+//! Per-ticker sequence tracking for the UDP quote stream.
+//!
+//! The server stamps every quote with a per-ticker sequence number. `SequenceTracker`
+//! compares each received number with the next expected one and classifies the datagram:
+//!
+//! - in order — exactly the next expected number;
+//! - gap — a jump forward; the skipped numbers are remembered as missing;
+//! - out of order — a number that was previously recorded as missing arrives late;
+//! - duplicate — a number at or below the last one that was not missing.
+//!
+//! Missing quotes can be filled in later through [`SequenceTracker::recover`], e.g. from a
+//! retransmission over the command channel. Counters are kept in `SequenceStats` and
+//! summarized when the client stops.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+/// Upper bound of missing sequence numbers remembered per ticker.
+const MAX_TRACKED_MISSING: usize = 10_000;
+
+/// Classification of a received sequence number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceEvent {
+    /// The first quote seen for this ticker, or the next expected one.
+    InOrder,
+    /// A jump forward; contains the first and last missing sequence numbers.
+    Gap {
+        /// First missing sequence number.
+        from: u64,
+        /// Last missing sequence number.
+        to: u64,
+    },
+    /// A previously missing quote arrived late.
+    OutOfOrder,
+    /// A quote that was already received.
+    Duplicate,
+}
+
+/// Counters accumulated over the lifetime of the stream.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SequenceStats {
+    /// Total quotes observed.
+    pub received: u64,
+    /// Number of forward jumps.
+    pub gaps: u64,
+    /// Quotes skipped by forward jumps.
+    pub missing: u64,
+    /// Late arrivals that filled a gap.
+    pub out_of_order: u64,
+    /// Missing quotes filled in by retransmission.
+    pub recovered: u64,
+    /// Quotes received more than once.
+    pub duplicates: u64,
+}
+
+impl fmt::Display for SequenceStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "received={} gaps={} missing={} out_of_order={} recovered={} duplicates={}",
+            self.received, self.gaps, self.missing, self.out_of_order, self.recovered, self.duplicates
+        )
+    }
+}
+
+/// Per-ticker state: last highest sequence number and the numbers still missing.
+#[derive(Default)]
+struct TickerSequence {
+    last: u64,
+    missing: BTreeSet<u64>,
+}
+
+/// Detects gaps, duplicates and reordering in per-ticker sequence numbers.
+#[derive(Default)]
+pub struct SequenceTracker {
+    tickers: HashMap<String, TickerSequence>,
+    stats: SequenceStats,
+}
+
+impl SequenceTracker {
+    /// Create an empty tracker.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record sequence number `seq` for `ticker` and classify it.
+    pub fn observe(&mut self, ticker: &str, seq: u64) -> SequenceEvent {
+        self.stats.received += 1;
+        let state = match self.tickers.get_mut(ticker) {
+            Some(state) => state,
+            None => {
+                self.tickers.insert(
+                    ticker.to_string(),
+                    TickerSequence { last: seq, missing: BTreeSet::new() },
+                );
+                return SequenceEvent::InOrder;
+            }
+        };
+
+        if state.last.checked_add(1) == Some(seq) {
+            state.last = seq;
+            SequenceEvent::InOrder
+        } else if seq > state.last {
+            // Both ends are in range since `last < seq - 1`.
+            let (from, to) = (state.last + 1, seq - 1);
+            // Only the newest numbers can still be remembered, so a jump of any size
+            // inserts at most `MAX_TRACKED_MISSING` of them.
+            let oldest_tracked = to.saturating_sub(MAX_TRACKED_MISSING as u64 - 1).max(from);
+            state.missing.extend(oldest_tracked..=to);
+            while state.missing.len() > MAX_TRACKED_MISSING {
+                state.missing.pop_first();
+            }
+            state.last = seq;
+            self.stats.gaps += 1;
+            self.stats.missing = self.stats.missing.saturating_add(to - from + 1);
+            SequenceEvent::Gap { from, to }
+        } else if state.missing.remove(&seq) {
+            self.stats.out_of_order += 1;
+            SequenceEvent::OutOfOrder
+        } else {
+            self.stats.duplicates += 1;
+            SequenceEvent::Duplicate
+        }
+    }
+
+    /// Fill in a missing quote obtained out of band (e.g. by retransmission).
+    ///
+    /// Returns `true` if `seq` was still missing; `false` if it already arrived or was
+    /// never part of a gap, in which case the quote should be ignored.
+    pub fn recover(&mut self, ticker: &str, seq: u64) -> bool {
+        let recovered = self
+            .tickers
+            .get_mut(ticker)
+            .is_some_and(|state| state.missing.remove(&seq));
+        if recovered {
+            self.stats.recovered += 1;
+        }
+        recovered
+    }
+
+    /// Stop tracking `ticker`, e.g. after it was removed from the subscription.
+    pub fn forget(&mut self, ticker: &str) {
+        self.tickers.remove(ticker);
+    }
+
+    /// Counters accumulated so far.
+    pub fn stats(&self) -> SequenceStats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn consecutive_numbers_are_in_order() {
+        let mut tracker = SequenceTracker::new();
+        assert_eq!(tracker.observe("AAPL", 5), SequenceEvent::InOrder);
+        assert_eq!(tracker.observe("AAPL", 6), SequenceEvent::InOrder);
+        // Every ticker is tracked on its own.
+        assert_eq!(tracker.observe("MSFT", 1), SequenceEvent::InOrder);
+        assert_eq!(tracker.observe("AAPL", 7), SequenceEvent::InOrder);
+
+        let stats = tracker.stats();
+        assert_eq!(stats.received, 4);
+        assert_eq!((stats.gaps, stats.missing, stats.duplicates, stats.out_of_order), (0, 0, 0, 0));
+    }
+
+    #[test]
+    fn a_jump_forward_is_a_gap() {
+        let mut tracker = SequenceTracker::new();
+        tracker.observe("AAPL", 1);
+        assert_eq!(tracker.observe("AAPL", 5), SequenceEvent::Gap { from: 2, to: 4 });
+        assert_eq!(tracker.observe("AAPL", 6), SequenceEvent::InOrder);
+
+        let stats = tracker.stats();
+        assert_eq!((stats.gaps, stats.missing), (1, 3));
+    }
+
+    #[test]
+    fn a_huge_jump_forward_tracks_only_the_newest_numbers() {
+        let mut tracker = SequenceTracker::new();
+        tracker.observe("AAPL", 0);
+        assert_eq!(tracker.observe("AAPL", u64::MAX), SequenceEvent::Gap { from: 1, to: u64::MAX - 1 });
+        assert_eq!(tracker.stats().missing, u64::MAX - 1);
+
+        // The number after `u64::MAX` does not exist, so a repeat is a duplicate.
+        assert_eq!(tracker.observe("AAPL", u64::MAX), SequenceEvent::Duplicate);
+        assert!(tracker.recover("AAPL", u64::MAX - MAX_TRACKED_MISSING as u64));
+        assert!(!tracker.recover("AAPL", u64::MAX - MAX_TRACKED_MISSING as u64 - 1));
+        assert!(!tracker.recover("AAPL", 1));
+    }
+
+    #[test]
+    fn a_missing_number_arriving_late_is_out_of_order() {
+        let mut tracker = SequenceTracker::new();
+        tracker.observe("AAPL", 1);
+        tracker.observe("AAPL", 4);
+        assert_eq!(tracker.observe("AAPL", 3), SequenceEvent::OutOfOrder);
+        // Once filled, the same number is a duplicate.
+        assert_eq!(tracker.observe("AAPL", 3), SequenceEvent::Duplicate);
+
+        let stats = tracker.stats();
+        assert_eq!((stats.out_of_order, stats.duplicates), (1, 1));
+    }
+
+    #[test]
+    fn repeated_numbers_are_duplicates() {
+        let mut tracker = SequenceTracker::new();
+        tracker.observe("AAPL", 1);
+        tracker.observe("AAPL", 2);
+        assert_eq!(tracker.observe("AAPL", 2), SequenceEvent::Duplicate);
+        assert_eq!(tracker.observe("AAPL", 1), SequenceEvent::Duplicate);
+        assert_eq!(tracker.stats().duplicates, 2);
+    }
+
+    #[test]
+    fn recovering_a_gap_closes_it() {
+        let mut tracker = SequenceTracker::new();
+        tracker.observe("AAPL", 1);
+        tracker.observe("AAPL", 4);
+        assert!(tracker.recover("AAPL", 2));
+        assert!(tracker.recover("AAPL", 3));
+
+        // Nothing is outstanding any more: a second copy is ignored, and so is a late
+        // datagram of the recovered range.
+        assert!(!tracker.recover("AAPL", 3));
+        assert_eq!(tracker.observe("AAPL", 2), SequenceEvent::Duplicate);
+        assert_eq!(tracker.observe("AAPL", 5), SequenceEvent::InOrder);
+
+        let stats = tracker.stats();
+        assert_eq!((stats.gaps, stats.missing, stats.recovered, stats.out_of_order), (1, 2, 2, 0));
+    }
+
+    #[test]
+    fn numbers_outside_a_gap_are_not_recovered() {
+        let mut tracker = SequenceTracker::new();
+        assert!(!tracker.recover("AAPL", 1));
+        tracker.observe("AAPL", 1);
+        tracker.observe("AAPL", 2);
+        assert!(!tracker.recover("AAPL", 2));
+        assert_eq!(tracker.stats().recovered, 0);
+    }
+
+    #[test]
+    fn a_forgotten_ticker_starts_over() {
+        let mut tracker = SequenceTracker::new();
+        tracker.observe("AAPL", 1);
+        tracker.forget("AAPL");
+        assert_eq!(tracker.observe("AAPL", 10), SequenceEvent::InOrder);
+        assert_eq!(tracker.stats().gaps, 0);
+    }
+}
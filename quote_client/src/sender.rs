@@ -1,49 +1,229 @@
-//! Sending commands to the quote server over UDP.
+//! Sending commands to the quote server.
 //!
-//! This module provides a small helper for encoding and sending `Command` messages
-//! and for running a background PING loop to keep the subscription alive.
-use log::{debug, error, info};
-use quote_common::command::Command;
+//! This module provides the TCP `CommandChannel` and a small helper for encoding and
+//! sending `Request` messages, for running a background PING loop to keep the subscription
+//! alive, for reading subscription changes typed on stdin, and for requesting
+//! retransmission of quotes lost on the data channel.
+use crate::model::sequence::SequenceTracker;
+use log::{debug, error, info, warn};
+use quote_common::command::{Endpoint, Request, Response};
+use quote_common::framing::{read_frame, read_message, write_message};
+use quote_common::quote::Quote;
+use quote_common::handshake::{Capabilities, Session, PROTOCOL_VERSION};
+use quote_common::tickers::Ticker;
 use quote_common::ParserError;
-use std::io::{ErrorKind, Write};
-use std::net::{TcpStream, UdpSocket};
+use std::io::{BufRead, ErrorKind};
+use std::net::{SocketAddr, TcpStream, UdpSocket};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
 use std::sync::{
     atomic::{AtomicBool, Ordering},
-    Arc,
+    Arc, Mutex,
 };
 use std::thread;
 use std::time::Duration;
 
-/// PING interval in milliseconds used by the background thread.
+/// PING interval in milliseconds used when the server did not return agreed terms.
 const INTERVAL_MS: u64 = 2000;
+/// How long to wait for the server's response to a command, in milliseconds.
+const RESPONSE_TIMEOUT_MS: u64 = 5000;
+/// How long to wait for the response to a `RETRANSMIT`, in milliseconds. Kept short since
+/// the command channel is held meanwhile and console commands queue behind it.
+const RETRANSMIT_TIMEOUT_MS: u64 = 1000;
+
+/// The client's TCP command connection.
+///
+/// Responses are normally read straight from the socket after each request. Once quotes are
+/// streamed over the same connection (`--transport tcp`), [`CommandChannel::split_quotes`]
+/// starts a reader thread that hands responses to the waiting request and everything else
+/// to the returned quote channel.
+///
+/// The server answers requests in order, so a response that arrives after its request timed
+/// out is recognized by counting: it is skipped by the next request instead of being taken
+/// for that request's answer.
+pub struct CommandChannel {
+    stream: TcpStream,
+    responses: Option<Receiver<Response>>,
+    /// Responses still owed for requests that timed out.
+    late_responses: usize,
+}
+
+impl CommandChannel {
+    /// Connect to the server's command port at `address`.
+    ///
+    /// Returns `ParserError::ServerUnavailable` if the server cannot be reached.
+    pub fn connect(address: &str) -> Result<Self, ParserError> {
+        let stream = TcpStream::connect(address)
+            .map_err(|e| ParserError::ServerUnavailable(format!("Failed to connect to server: {}", e)))?;
+        Ok(Self { stream, responses: None, late_responses: 0 })
+    }
+
+    /// Local address of the connection.
+    pub fn local_addr(&self) -> Result<SocketAddr, ParserError> {
+        Ok(self.stream.local_addr()?)
+    }
+
+    /// Start demultiplexing incoming frames and return the channel of quote payloads.
+    ///
+    /// The channel closes when the server closes the connection. Quotes that arrive after
+    /// the receiver was dropped are discarded, so that the responses to the final commands
+    /// (such as `UNSUBSCRIBE` on exit) still get through.
+    pub fn split_quotes(&mut self) -> Result<Receiver<Vec<u8>>, ParserError> {
+        let mut reader = self.stream.try_clone()?;
+        let (response_tx, response_rx) = mpsc::channel();
+        let (quote_tx, quote_rx) = mpsc::channel();
+        thread::spawn(move || {
+            loop {
+                let sent = match read_frame(&mut reader) {
+                    Ok(Some(frame)) => match Response::from_frame(&frame) {
+                        Some(response) => response_tx.send(response).is_ok(),
+                        None => {
+                            if quote_tx.send(frame).is_err() {
+                                debug!("Quote receiver stopped; discarding a quote frame");
+                            }
+                            true
+                        }
+                    },
+                    Ok(None) => {
+                        info!("Server closed the connection");
+                        false
+                    }
+                    Err(e) => {
+                        error!("Failed to read from the server: {}", e);
+                        false
+                    }
+                };
+                if !sent {
+                    break;
+                }
+            }
+        });
+        self.responses = Some(response_rx);
+        Ok(quote_rx)
+    }
+
+    /// Write `request` as a single length-prefixed frame.
+    fn send(&mut self, request: &Request) -> Result<(), ParserError> {
+        write_message(&mut self.stream, request)
+    }
+
+    /// Wait up to `timeout` for the `Response` to the last request sent, skipping the late
+    /// responses to earlier requests that timed out.
+    fn read_response(&mut self, timeout: Duration) -> Result<Response, ParserError> {
+        loop {
+            let Some(response) = self.next_response(timeout)? else {
+                self.late_responses += 1;
+                return Err(ParserError::ServerUnavailable(format!(
+                    "no response within {} ms",
+                    timeout.as_millis()
+                )));
+            };
+            if self.late_responses == 0 {
+                return Ok(response);
+            }
+            self.late_responses -= 1;
+            debug!("Discarding a late response to an earlier request: {:?}", response);
+        }
+    }
+
+    /// Wait up to `timeout` for the next `Response`; `None` on timeout.
+    fn next_response(&mut self, timeout: Duration) -> Result<Option<Response>, ParserError> {
+        let closed = || ParserError::ServerUnavailable("connection closed before a response was received".to_string());
+        if let Some(responses) = &self.responses {
+            return match responses.recv_timeout(timeout) {
+                Ok(response) => Ok(Some(response)),
+                Err(RecvTimeoutError::Timeout) => Ok(None),
+                Err(RecvTimeoutError::Disconnected) => Err(closed()),
+            };
+        }
+
+        self.stream.set_read_timeout(Some(timeout))?;
+        let result = read_message::<_, Response>(&mut self.stream);
+        self.stream.set_read_timeout(None)?;
+
+        match result {
+            Ok(Some(response)) => Ok(Some(response)),
+            Ok(None) => Err(closed()),
+            Err(ParserError::Io(e))
+                if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
 
 /// Helper type for sending commands to the server.
 pub struct CommandSender;
 
 impl CommandSender {
-    pub fn send_command(stream: &mut TcpStream, command: &Command) -> Result<(), ParserError> {
-        let tickers_str: Vec<String> = command.tickers.iter().map(|t| t.to_string()).collect();
-        let command_text = format!(
-            "STREAM udp://{}:{} {}\n",
-            command.address,
-            command.port,
-            tickers_str.join(",")
-        );
-        let com = serde_json::to_vec(&command)?;
+    /// Send `request` to the server over `channel` and wait for its `Response`.
+    ///
+    /// Returns `ParserError::ServerUnavailable` if the server does not answer within
+    /// `RESPONSE_TIMEOUT_MS` and `ParserError::SubscriptionRejected` if it refused the
+    /// subscription.
+    pub fn send_command(channel: &mut CommandChannel, request: &Request) -> Result<Response, ParserError> {
+        Self::send_command_within(channel, request, Duration::from_millis(RESPONSE_TIMEOUT_MS))
+    }
+
+    /// Like [`CommandSender::send_command`], but waits at most `timeout` for the response.
+    fn send_command_within(
+        channel: &mut CommandChannel,
+        request: &Request,
+        timeout: Duration,
+    ) -> Result<Response, ParserError> {
+        let tickers_str: Vec<String> = request.tickers().iter().map(|t| t.to_string()).collect();
+        let target = request
+            .endpoint()
+            .map(|e| format!("{}://{}", e.transport, e.socket_addr()))
+            .unwrap_or_default();
+        let command_text = format!("{} {} {}\n", request.verb(), target, tickers_str.join(","));
 
         info!("Sending command: {}", command_text.trim());
-        stream.write_all(&com)?;
-        Ok(())
+        channel.send(request)?;
+
+        let response = channel.read_response(timeout)?;
+        if !response.is_success() {
+            let mut reasons: Vec<String> = response
+                .rejected
+                .iter()
+                .map(|r| format!("{} ({})", r.ticker, r.reason))
+                .collect();
+            reasons.extend(response.error);
+            return Err(ParserError::SubscriptionRejected(reasons.join(", ")));
+        }
+        Ok(response)
+    }
+
+    /// Open the session with a `HELLO` carrying this build's version and capabilities and
+    /// return what the server agreed to.
+    pub fn handshake(channel: &mut CommandChannel) -> Result<Session, ParserError> {
+        let hello = Request::Hello {
+            version: PROTOCOL_VERSION,
+            capabilities: Capabilities::supported(),
+        };
+        info!("Sending command: HELLO v{}", PROTOCOL_VERSION);
+        channel.send(&hello)?;
+
+        let response = channel.read_response(Duration::from_millis(RESPONSE_TIMEOUT_MS))?;
+        match (response.session, response.error) {
+            (Some(session), None) => Ok(session),
+            (_, Some(error)) => Err(ParserError::Handshake(error)),
+            (None, None) => Err(ParserError::Handshake("server did not return a session".to_string())),
+        }
     }
+
+    /// Spawn a background thread that sends a `PING` datagram to `target_addr` every
+    /// `interval` (as agreed with the server, or `INTERVAL_MS` if none was agreed).
     pub fn start_ping_thread(
         socket: Arc<UdpSocket>,
         target_addr: String,
-        _ping_command: Command,
+        interval: Option<Duration>,
         shutdown: Arc<AtomicBool>,
     ) {
-        info!("Ping thread started. Target: {}", target_addr);
+        let interval = interval.unwrap_or(Duration::from_millis(INTERVAL_MS));
+        info!("Ping thread started. Target: {}, interval: {:?}", target_addr, interval);
         thread::spawn(move || {
-            let interval = Duration::from_millis(INTERVAL_MS);
             while !shutdown.load(Ordering::Relaxed) {
                 thread::sleep(interval);
                 if shutdown.load(Ordering::Relaxed) {
@@ -64,4 +244,169 @@ impl CommandSender {
             info!("Ping thread stopping...");
         });
     }
+
+    /// Spawn a background thread that reads subscription changes from stdin and sends
+    /// them over `channel`.
+    ///
+    /// Supported lines:
+    /// - `add AAPL,MSFT` — add tickers to the live subscription;
+    /// - `remove TSLA` — remove tickers from it;
+    /// - `depth AAPL` / `nodepth AAPL` — start or stop following the order books of tickers;
+    /// - `unsubscribe` — send `UNSUBSCRIBE` at once, set `unsubscribed` if the server
+    ///   confirmed it, and stop the client.
+    ///
+    /// Removed tickers are dropped from `tracker` so re-adding them later does not show up
+    /// as a sequence gap.
+    pub fn start_console_thread(
+        channel: Arc<Mutex<CommandChannel>>,
+        endpoint: Endpoint,
+        tracker: Option<Arc<Mutex<SequenceTracker>>>,
+        unsubscribed: Arc<AtomicBool>,
+        shutdown: Arc<AtomicBool>,
+    ) {
+        thread::spawn(move || {
+            let stdin = std::io::stdin();
+            for line in stdin.lock().lines() {
+                let Ok(line) = line else { break };
+                let mut parts = line.trim().splitn(2, char::is_whitespace);
+                let verb = parts.next().unwrap_or_default().to_ascii_lowercase();
+                let rest = parts.next().unwrap_or_default();
+
+                let request = match verb.as_str() {
+                    "" => continue,
+                    "unsubscribe" | "quit" => {
+                        // Stop the stream now rather than when the receiver next wakes up.
+                        let request = Request::Unsubscribe { endpoint };
+                        let result = channel
+                            .lock()
+                            .map_err(ParserError::from)
+                            .and_then(|mut channel| Self::send_command(&mut channel, &request));
+                        match result {
+                            Ok(_) => unsubscribed.store(true, Ordering::SeqCst),
+                            Err(e) => error!("{} failed: {}", request.verb(), e),
+                        }
+                        shutdown.store(true, Ordering::SeqCst);
+                        break;
+                    }
+                    "add" | "remove" | "depth" | "nodepth" => match parse_tickers(rest) {
+                        Ok(tickers) if verb == "add" => Request::AddTickers { endpoint, tickers },
+                        Ok(tickers) if verb == "remove" => Request::RemoveTickers { endpoint, tickers },
+                        Ok(tickers) if verb == "depth" => Request::AddDepth { endpoint, tickers },
+                        Ok(tickers) => Request::RemoveDepth { endpoint, tickers },
+                        Err(e) => {
+                            warn!("{}", e);
+                            continue;
+                        }
+                    },
+                    _ => {
+                        warn!("Unknown console command: {} (expected add, remove, depth, nodepth or unsubscribe)", verb);
+                        continue;
+                    }
+                };
+
+                let result = channel
+                    .lock()
+                    .map_err(ParserError::from)
+                    .and_then(|mut channel| Self::send_command(&mut channel, &request));
+                match result {
+                    Ok(response) => {
+                        info!("Subscription now streams {:?}", response.accepted);
+                        if matches!(request, Request::AddDepth { .. } | Request::RemoveDepth { .. }) {
+                            info!("Following the order books of {:?}", response.depth);
+                        }
+                        if let (Request::RemoveTickers { tickers, .. }, Some(tracker)) = (&request, &tracker)
+                            && let Ok(mut tracker) = tracker.lock()
+                        {
+                            for ticker in tickers {
+                                tracker.forget(&ticker.to_string());
+                            }
+                        }
+                    }
+                    Err(e) => error!("{} failed: {}", request.verb(), e),
+                }
+            }
+        });
+    }
+
+    /// Spawn a background thread that requests retransmission of missing quotes.
+    ///
+    /// Every `GapFill` received on `gap_rx` becomes a `RETRANSMIT` request over `channel`.
+    /// The channel is held for at most `RETRANSMIT_TIMEOUT_MS` per request and released
+    /// between requests, so console commands are not stuck behind a burst of gaps.
+    /// Quotes that are still missing in `tracker` are printed through `on_quote`; quotes
+    /// that arrived in the meantime are ignored. The thread ends when `gap_rx` is closed.
+    pub fn start_gap_fill_thread<F>(
+        channel: Arc<Mutex<CommandChannel>>,
+        endpoint: Endpoint,
+        tracker: Arc<Mutex<SequenceTracker>>,
+        gap_rx: Receiver<GapFill>,
+        on_quote: F,
+    ) where
+        F: Fn(&Quote) + Send + 'static,
+    {
+        thread::spawn(move || {
+            for gap in gap_rx {
+                let Ok(ticker) = gap.ticker.parse::<Ticker>() else {
+                    warn!("Cannot request retransmission for unknown ticker {}", gap.ticker);
+                    continue;
+                };
+                let request = Request::Retransmit {
+                    endpoint,
+                    ticker,
+                    from_seq: gap.from,
+                    to_seq: gap.to,
+                };
+                let timeout = Duration::from_millis(RETRANSMIT_TIMEOUT_MS);
+                let result = channel
+                    .lock()
+                    .map_err(ParserError::from)
+                    .and_then(|mut channel| Self::send_command_within(&mut channel, &request, timeout));
+
+                let quotes = match result {
+                    Ok(response) => response.quotes,
+                    Err(e) => {
+                        error!("RETRANSMIT {} {}..={} failed: {}", gap.ticker, gap.from, gap.to, e);
+                        continue;
+                    }
+                };
+                let Ok(mut tracker) = tracker.lock() else { break };
+                let mut recovered = 0;
+                for quote in quotes.iter().filter(|q| tracker.recover(&q.ticker, q.seq)) {
+                    on_quote(quote);
+                    recovered += 1;
+                }
+                info!(
+                    "RETRANSMIT {} {}..={}: recovered {} of {} ({})",
+                    gap.ticker,
+                    gap.from,
+                    gap.to,
+                    recovered,
+                    gap.to - gap.from + 1,
+                    tracker.stats()
+                );
+            }
+        });
+    }
+}
+
+/// A range of sequence numbers the receiver found missing for one ticker.
+#[derive(Debug, Clone)]
+pub struct GapFill {
+    /// Ticker with missing quotes.
+    pub ticker: String,
+    /// First missing sequence number.
+    pub from: u64,
+    /// Last missing sequence number.
+    pub to: u64,
+}
+
+/// Parse tickers separated by commas or whitespace.
+fn parse_tickers(text: &str) -> Result<Vec<Ticker>, ParserError> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            s.parse::<Ticker>()
+                .map_err(|e| ParserError::ParseTickersFile(format!("{}: {}", s, e)))
+        })
+        .collect()
 }
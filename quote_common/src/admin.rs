@@ -0,0 +1,126 @@
+//! Operator protocol for inspecting and controlling a running server.
+//!
+//! The server accepts `AdminRequest`s on a separate admin TCP port that listens on
+//! localhost by default (`ADMIN_PORT`). Messages use the same length-prefixed JSON framing
+//! as the command channel, and every request is answered with one `AdminResponse`:
+//!
+//! ```json
+//! {"header":"KICK","client":"127.0.0.1:55555"}
+//! ```
+//!
+//! - `LIST_CLIENTS` — every live subscription with its tickers, age and last ping;
+//! - `KICK` — end a client's stream with a going-away notice;
+//! - `PAUSE_TICKER` / `RESUME_TICKER` — stop or restart quote generation for a ticker;
+//! - `STATS` — generator counters and per-ticker state.
+use std::net::SocketAddr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::command::Transport;
+use crate::tickers::Ticker;
+
+/// Request sent by an operator to the server's admin port.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "header", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AdminRequest {
+    /// List every live client subscription.
+    ListClients,
+    /// End the stream of the client at `client`.
+    Kick {
+        /// Address of the client's stream, as shown by `LIST_CLIENTS`.
+        client: SocketAddr,
+    },
+    /// Stop generating quotes for `ticker`; its price and sequence number stay frozen.
+    PauseTicker {
+        /// Ticker to pause.
+        ticker: Ticker,
+    },
+    /// Generate quotes for a paused `ticker` again.
+    ResumeTicker {
+        /// Ticker to resume.
+        ticker: Ticker,
+    },
+    /// Report generator counters and per-ticker state.
+    Stats,
+}
+
+/// A live client subscription as seen by the server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientInfo {
+    /// Address the client's quotes are sent to; identifies the client in `KICK`.
+    pub address: SocketAddr,
+    /// Transport of the quote stream.
+    pub transport: Transport,
+    /// Identifier reported to the client when it subscribed.
+    pub subscription_id: u64,
+    /// Tickers currently streamed to the client.
+    pub tickers: Vec<Ticker>,
+    /// Tickers whose order books are streamed to the client.
+    #[serde(default)]
+    pub depth: Vec<Ticker>,
+    /// Time since the client subscribed, in milliseconds.
+    pub age_ms: u64,
+    /// Time since the client's last `PING`, in milliseconds; `None` for TCP streams and for
+    /// UDP clients that have not pinged yet.
+    pub last_ping_ms: Option<u64>,
+    /// Quotes discarded by the client's overflow policy so far.
+    pub dropped_quotes: u64,
+}
+
+/// State of one ticker in the generator.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TickerStats {
+    /// Ticker symbol.
+    pub ticker: Ticker,
+    /// Current mid price; quotes are bid and offered around it.
+    pub price: f64,
+    /// Sequence number of the last generated quote.
+    pub seq: u64,
+    /// Subscribers the ticker's quotes are routed to, including the server's quote history.
+    pub subscribers: usize,
+    /// Quote generation is paused for the ticker.
+    pub paused: bool,
+}
+
+/// Counters of the quote generator.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeneratorStats {
+    /// Time since the generator started, in milliseconds.
+    pub uptime_ms: u64,
+    /// Interval between ticks, in milliseconds.
+    pub tick_interval_ms: u64,
+    /// Ticks since the generator started.
+    pub ticks: u64,
+    /// Quotes generated since the generator started.
+    pub quotes: u64,
+    /// Subscribers registered with the generator, including the server's quote history.
+    pub subscribers: usize,
+    /// Per-ticker state, in configuration order.
+    pub tickers: Vec<TickerStats>,
+}
+
+/// Server reply to an `AdminRequest`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum AdminResponse {
+    /// Reply to `LIST_CLIENTS`.
+    Clients {
+        /// Live subscriptions, oldest first.
+        clients: Vec<ClientInfo>,
+    },
+    /// Reply to `STATS`.
+    Stats {
+        /// Generator counters.
+        stats: GeneratorStats,
+    },
+    /// The request was applied.
+    Done {
+        /// What the server did.
+        message: String,
+    },
+    /// The request could not be applied.
+    Error {
+        /// Why the request failed.
+        message: String,
+    },
+}
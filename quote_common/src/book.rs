@@ -0,0 +1,116 @@
+//! Level 2 order book messages sent on the data channel.
+//!
+//! Clients that subscribe to depth for a ticker receive `BookMessage`s describing the
+//! server's simulated limit order book of that ticker, next to its quotes. A book has up to
+//! `MAX_BOOK_LEVELS` price levels per side:
+//!
+//! - `BOOK_SNAPSHOT` — the full book, best levels first. One is sent when depth is
+//!   subscribed, and then one for every book periodically.
+//! - `BOOK_UPDATE` — level changes since the previous message. A change gives the new size
+//!   of a price level; size `0` removes the level.
+//!
+//! Every update carries a per-ticker book sequence number one higher than the previous
+//! update, and a snapshot carries the number of the last update it includes. A client that
+//! sees a number other than the next one missed an update, and must ignore updates until
+//! the next snapshot.
+//!
+//! Like `StreamNotice`s, book messages are JSON objects tagged by `event` regardless of the
+//! stream's encoding, and each one fits in a single datagram.
+
+use serde::{Deserialize, Serialize};
+
+use crate::ParserError;
+
+/// Most price levels per side of a book.
+pub const MAX_BOOK_LEVELS: usize = 20;
+/// Most level changes carried by one `BOOK_UPDATE`; larger changes are split.
+pub const MAX_BOOK_CHANGES: usize = 24;
+
+/// Side of the book.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    /// Buy orders.
+    Bid,
+    /// Sell orders.
+    Ask,
+}
+
+/// Resting size at one price.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BookLevel {
+    /// Price of the level.
+    pub price: f64,
+    /// Shares resting at `price`.
+    pub size: u32,
+}
+
+/// New size of one price level.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LevelChange {
+    /// Side of the level.
+    pub side: Side,
+    /// Price of the level.
+    pub price: f64,
+    /// Shares now resting at `price`; `0` removes the level.
+    pub size: u32,
+}
+
+/// Order book message for one ticker.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum BookMessage {
+    /// The full book.
+    BookSnapshot {
+        /// Symbol identifier (string form of `Ticker`).
+        ticker: String,
+        /// Sequence number of the last update included in the snapshot.
+        seq: u64,
+        /// UTC timestamp in milliseconds since Unix epoch.
+        timestamp: u64,
+        /// Bid levels, highest price first.
+        bids: Vec<BookLevel>,
+        /// Ask levels, lowest price first.
+        asks: Vec<BookLevel>,
+    },
+    /// Changes since the previous message.
+    BookUpdate {
+        /// Symbol identifier (string form of `Ticker`).
+        ticker: String,
+        /// Per-ticker book sequence number.
+        seq: u64,
+        /// UTC timestamp in milliseconds since Unix epoch.
+        timestamp: u64,
+        /// Changed levels.
+        changes: Vec<LevelChange>,
+    },
+}
+
+impl BookMessage {
+    /// Ticker the message describes.
+    pub fn ticker(&self) -> &str {
+        match self {
+            BookMessage::BookSnapshot { ticker, .. } | BookMessage::BookUpdate { ticker, .. } => ticker,
+        }
+    }
+
+    /// Book sequence number of the message.
+    pub fn seq(&self) -> u64 {
+        match self {
+            BookMessage::BookSnapshot { seq, .. } | BookMessage::BookUpdate { seq, .. } => *seq,
+        }
+    }
+
+    /// Encode the message as a JSON datagram or frame payload.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ParserError> {
+        Ok(serde_json::to_vec(self)?)
+    }
+
+    /// Decode a book message, returning `None` for quote payloads and anything else.
+    pub fn from_slice(bytes: &[u8]) -> Option<Self> {
+        if bytes.first() != Some(&b'{') {
+            return None;
+        }
+        serde_json::from_slice(bytes).ok()
+    }
+}
@@ -1,66 +1,399 @@
-//! Shared protocol command type used by client and server.
+//! Shared protocol types used by client and server.
 //!
-//! A `Command` can either be a subscription request (`J_QUOTE`) with a list of
-//! tickers or a keep-alive `PING` message. Values are serialized with `bincode`
-//! for compact transmission.
-use std::net::SocketAddr;
+//! A `Request` is a typed, serde-tagged message sent from the client on the TCP command
+//! channel. It can be a `HELLO` handshake (see `handshake`), a subscription request
+//! (`J_QUOTE`) with a list of tickers, a change to a live subscription (`ADD_TICKERS`,
+//! `REMOVE_TICKERS`, `ADD_DEPTH`, `REMOVE_DEPTH`, `UNSUBSCRIBE`), a keep-alive `PING` or a
+//! `RETRANSMIT` of quotes the client missed on the data channel. On the wire the verb is
+//! carried in the `header` field:
+//!
+//! ```json
+//! {"header":"J_QUOTE","transport":"udp","address":"0.0.0.0","port":55555,"tickers":["AAPL"]}
+//! ```
+//!
+//! A `J_QUOTE` may ask for conflation (`conflate_ms` or `max_updates_per_sec`), in which
+//! case the server sends only the latest quote of each ticker once per interval.
+//!
+//! Order book depth (see `book`) is subscribed per ticker, separately from quotes: with
+//! the `depth` list of `J_QUOTE`, or later with `ADD_DEPTH` and `REMOVE_DEPTH`.
+//!
+//! The server answers every request with a `Response` that lists the accepted
+//! and rejected tickers, the assigned subscription id and the server version.
+//!
+//! A subscription with the `tcp` transport streams quotes back as frames on the same
+//! connection, interleaved with the responses; `Response::from_frame` tells them apart.
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
+use strum_macros::Display;
 
+use crate::error::ParserError;
+use crate::handshake::{Capabilities, Encoding, Session};
+use crate::quote::Quote;
 use crate::tickers::Ticker;
 
-/// Header value for subscription commands.
-pub const HEADER: &str = "J_QUOTE";
-/// Header value for keep-alive pings.
-pub const PING: &str = "PING";
-/// Transport kind (currently UDP).
-/// Keep the lowercase to match the existing client value.
-pub const CONNECTION: &str = "udp";
+/// Most quotes returned by a single `RETRANSMIT`.
+pub const MAX_RETRANSMIT_QUOTES: u64 = 1000;
+
+/// Transport over which quotes are delivered to the client.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Display, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Transport {
+    /// Quotes are pushed as UDP datagrams to the client's data port.
+    #[default]
+    Udp,
+    /// Quotes are streamed as frames over the client's command connection. Closing the
+    /// connection ends the subscription.
+    Tcp,
+}
+
+/// Where the client expects to receive quotes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Endpoint {
+    /// Transport used for the quote stream.
+    pub transport: Transport,
+    /// Client IP address as seen by the client itself.
+    pub address: IpAddr,
+    /// Client data port.
+    pub port: u16,
+}
+
+impl Endpoint {
+    /// Creates a new endpoint description.
+    pub fn new(transport: Transport, address: IpAddr, port: u16) -> Self {
+        Self { transport, address, port }
+    }
+
+    /// Build the socket address from the fields.
+    pub fn socket_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.address, self.port)
+    }
+}
+
+/// Keep-alive terms agreed for a UDP subscription.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PingTerms {
+    /// Interval at which the client must send `PING`s, in milliseconds.
+    pub interval_ms: u64,
+    /// Silence after which the server drops the subscription, in milliseconds.
+    pub timeout_ms: u64,
+}
+
+/// Request sent from the client to the server on the TCP command channel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "header")]
+pub enum Request {
+    /// Announce the client's protocol version and capabilities.
+    #[serde(rename = "HELLO")]
+    Hello {
+        /// Highest protocol version the client speaks.
+        version: u16,
+        /// Features the client supports.
+        capabilities: Capabilities,
+    },
+    /// Start streaming `tickers` to `endpoint`.
+    #[serde(rename = "J_QUOTE")]
+    Subscribe {
+        /// Where quotes should be delivered.
+        #[serde(flatten)]
+        endpoint: Endpoint,
+        /// Tickers to subscribe to.
+        tickers: Vec<Ticker>,
+        /// Tickers whose order book to stream as well.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        depth: Vec<Ticker>,
+        /// Encoding of quote datagrams; must be one of the negotiated encodings.
+        #[serde(default)]
+        encoding: Encoding,
+        /// Longest time a quote may wait to be batched with later ones, in milliseconds.
+        /// Only used when batching was negotiated; `0` batches only quotes that are ready
+        /// at the same time.
+        #[serde(default)]
+        max_batch_delay_ms: u64,
+        /// Conflation interval in milliseconds: send only the latest quote of each ticker
+        /// once per interval. `0` sends every tick.
+        #[serde(default)]
+        conflate_ms: u64,
+        /// Most updates per second per ticker, conflating the rest; an alternative to
+        /// `conflate_ms`. `0` means unlimited.
+        #[serde(default)]
+        max_updates_per_sec: u32,
+        /// How often the client intends to send `PING`s, in milliseconds. The server may
+        /// adjust it and returns the agreed value in `Response::ping`.
+        #[serde(default)]
+        ping_interval_ms: Option<u64>,
+        /// Silence after which the client wants the server to drop the subscription, in
+        /// milliseconds; the server's default when absent.
+        #[serde(default)]
+        ping_timeout_ms: Option<u64>,
+    },
+    /// Keep-alive for the subscription streaming to `endpoint`.
+    #[serde(rename = "PING")]
+    Ping {
+        /// Endpoint of the subscription being kept alive.
+        #[serde(flatten)]
+        endpoint: Endpoint,
+    },
+    /// Tear down the subscription streaming to `endpoint`.
+    #[serde(rename = "UNSUBSCRIBE")]
+    Unsubscribe {
+        /// Endpoint of the subscription to stop.
+        #[serde(flatten)]
+        endpoint: Endpoint,
+    },
+    /// Add `tickers` to the live subscription streaming to `endpoint`.
+    #[serde(rename = "ADD_TICKERS")]
+    AddTickers {
+        /// Endpoint of the subscription to change.
+        #[serde(flatten)]
+        endpoint: Endpoint,
+        /// Tickers to add.
+        tickers: Vec<Ticker>,
+    },
+    /// Remove `tickers` from the live subscription streaming to `endpoint`.
+    #[serde(rename = "REMOVE_TICKERS")]
+    RemoveTickers {
+        /// Endpoint of the subscription to change.
+        #[serde(flatten)]
+        endpoint: Endpoint,
+        /// Tickers to remove.
+        tickers: Vec<Ticker>,
+    },
+    /// Stream the order books of `tickers` on the live subscription to `endpoint`.
+    #[serde(rename = "ADD_DEPTH")]
+    AddDepth {
+        /// Endpoint of the subscription to change.
+        #[serde(flatten)]
+        endpoint: Endpoint,
+        /// Tickers whose books to add.
+        tickers: Vec<Ticker>,
+    },
+    /// Stop streaming the order books of `tickers` to `endpoint`.
+    #[serde(rename = "REMOVE_DEPTH")]
+    RemoveDepth {
+        /// Endpoint of the subscription to change.
+        #[serde(flatten)]
+        endpoint: Endpoint,
+        /// Tickers whose books to remove.
+        tickers: Vec<Ticker>,
+    },
+    /// Resend quotes of `ticker` with sequence numbers `from_seq..=to_seq`.
+    ///
+    /// The server answers from a bounded history; at most `MAX_RETRANSMIT_QUOTES` quotes
+    /// are returned and quotes that fell out of the history are omitted.
+    #[serde(rename = "RETRANSMIT")]
+    Retransmit {
+        /// Endpoint of the subscription that missed the quotes.
+        #[serde(flatten)]
+        endpoint: Endpoint,
+        /// Ticker whose quotes are missing.
+        ticker: Ticker,
+        /// First missing sequence number.
+        from_seq: u64,
+        /// Last missing sequence number.
+        to_seq: u64,
+    },
+}
+
+impl Request {
+    /// Conflation interval requested by a `J_QUOTE`: the longer of `conflate_ms` and the
+    /// interval implied by `max_updates_per_sec`, or `None` to send every tick.
+    pub fn conflation_interval(conflate_ms: u64, max_updates_per_sec: u32) -> Option<Duration> {
+        let by_rate = match max_updates_per_sec {
+            0 => Duration::ZERO,
+            rate => Duration::from_secs(1) / rate,
+        };
+        let interval = Duration::from_millis(conflate_ms).max(by_rate);
+        (!interval.is_zero()).then_some(interval)
+    }
+
+    /// Wire names of all verbs understood by the server.
+    pub const VERBS: [&'static str; 9] = [
+        "HELLO",
+        "J_QUOTE",
+        "PING",
+        "UNSUBSCRIBE",
+        "ADD_TICKERS",
+        "REMOVE_TICKERS",
+        "ADD_DEPTH",
+        "REMOVE_DEPTH",
+        "RETRANSMIT",
+    ];
 
-/// Command payload sent between client and server.
+    /// Wire name of this request's verb.
+    pub fn verb(&self) -> &'static str {
+        match self {
+            Request::Hello { .. } => "HELLO",
+            Request::Subscribe { .. } => "J_QUOTE",
+            Request::Ping { .. } => "PING",
+            Request::Unsubscribe { .. } => "UNSUBSCRIBE",
+            Request::AddTickers { .. } => "ADD_TICKERS",
+            Request::RemoveTickers { .. } => "REMOVE_TICKERS",
+            Request::AddDepth { .. } => "ADD_DEPTH",
+            Request::RemoveDepth { .. } => "REMOVE_DEPTH",
+            Request::Retransmit { .. } => "RETRANSMIT",
+        }
+    }
+
+    /// Endpoint the request refers to (`None` for `HELLO`).
+    pub fn endpoint(&self) -> Option<&Endpoint> {
+        match self {
+            Request::Hello { .. } => None,
+            Request::Subscribe { endpoint, .. }
+            | Request::Ping { endpoint }
+            | Request::Unsubscribe { endpoint }
+            | Request::AddTickers { endpoint, .. }
+            | Request::RemoveTickers { endpoint, .. }
+            | Request::AddDepth { endpoint, .. }
+            | Request::RemoveDepth { endpoint, .. }
+            | Request::Retransmit { endpoint, .. } => Some(endpoint),
+        }
+    }
+
+    /// Tickers carried by the request (empty for `HELLO`, `PING` and `UNSUBSCRIBE`). For a
+    /// `J_QUOTE` these are the quote tickers, without its `depth`.
+    pub fn tickers(&self) -> &[Ticker] {
+        match self {
+            Request::Subscribe { tickers, .. }
+            | Request::AddTickers { tickers, .. }
+            | Request::RemoveTickers { tickers, .. }
+            | Request::AddDepth { tickers, .. }
+            | Request::RemoveDepth { tickers, .. } => tickers,
+            Request::Retransmit { ticker, .. } => std::slice::from_ref(ticker),
+            Request::Hello { .. } | Request::Ping { .. } | Request::Unsubscribe { .. } => &[],
+        }
+    }
+
+    /// Decode a request from JSON bytes.
+    ///
+    /// Returns `ParserError::UnknownVerb` if the `header` names a verb the server does not
+    /// know, and `ParserError::SerdeJson` for any other malformed payload.
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, ParserError> {
+        let value: serde_json::Value = serde_json::from_slice(bytes)?;
+        match value.get("header").and_then(|h| h.as_str()) {
+            Some(verb) if !Self::VERBS.contains(&verb) => {
+                Err(ParserError::UnknownVerb(verb.to_string()))
+            }
+            _ => Ok(serde_json::from_value(value)?),
+        }
+    }
+}
+
+/// Reason why the server refused to stream a requested ticker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display)]
+pub enum RejectReason {
+    /// The symbol is not a valid ticker (e.g., `UNKNOWN`).
+    #[strum(to_string = "unknown ticker")]
+    UnknownTicker,
+    /// The symbol is valid but the server does not generate quotes for it.
+    #[strum(to_string = "ticker is not generated by this server")]
+    NotGenerated,
+}
+
+/// A ticker the server refused together with the reason.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Command {
-    /// Command kind. Either `J_QUOTE` or `PING`.
-    pub header: String,
-    /// Transport protocol name (e.g., `udp`).
-    pub connection: String,
-    /// IP address.
-    pub address: String,
-    /// Port as a string.
-    pub port: String,
-    /// List of tickers to subscribe to (empty for `PING`).
-    pub tickers: Vec<Ticker>,
+pub struct RejectedTicker {
+    /// Requested symbol.
+    pub ticker: Ticker,
+    /// Why the symbol was refused.
+    pub reason: RejectReason,
 }
 
-impl Command {
-    /// Creates a new subscription (`J_QUOTE`) command.
-    pub fn new(address: &str, port: &str, tickers: Vec<Ticker>) -> Self {
-        Command {
-            header: String::from(HEADER),
-            connection: String::from(CONNECTION),
-            address: String::from(address),
-            port: String::from(port),
-            tickers,
+/// Server reply to a `Request`.
+///
+/// A subscription is all-or-nothing: if any ticker is rejected, no stream is created and
+/// `subscription_id` is `None`. Replies to `ADD_TICKERS`/`REMOVE_TICKERS`/`PING` list
+/// the resulting ticker set in `accepted`, and replies about a live subscription list its
+/// depth tickers in `depth`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Response {
+    /// Tickers the server is able to stream.
+    pub accepted: Vec<Ticker>,
+    /// Tickers whose order books are streamed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depth: Vec<Ticker>,
+    /// Tickers the server refused, with reasons.
+    pub rejected: Vec<RejectedTicker>,
+    /// Identifier of the created subscription, if any.
+    pub subscription_id: Option<u64>,
+    /// Version of the server that produced this response.
+    pub server_version: String,
+    /// Reason the command failed when it is not about individual tickers
+    /// (e.g., an unknown verb or no live subscription to modify).
+    #[serde(default)]
+    pub error: Option<String>,
+    /// Negotiated session; set only in replies to `HELLO`.
+    #[serde(default)]
+    pub session: Option<Session>,
+    /// Retransmitted quotes; set only in replies to `RETRANSMIT`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub quotes: Vec<Quote>,
+    /// Agreed keep-alive terms; set only in replies to a UDP `J_QUOTE`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ping: Option<PingTerms>,
+}
+
+impl Response {
+    /// Creates a response for a request that failed as a whole.
+    pub fn failure(server_version: &str, error: String) -> Self {
+        Response {
+            accepted: Vec::new(),
+            depth: Vec::new(),
+            rejected: Vec::new(),
+            subscription_id: None,
+            server_version: server_version.to_string(),
+            error: Some(error),
+            session: None,
+            quotes: Vec::new(),
+            ping: None,
         }
     }
 
-    /// Creates a new keep-alive `PING` command.
-    pub fn new_ping(address: &str, port: &str) -> Self {
-        Command {
-            header: String::from(PING),
-            connection: String::from(CONNECTION),
-            address: String::from(address),
-            port: String::from(port),
-            tickers: Vec::new(),
+    /// Creates the reply to a successful `HELLO`.
+    pub fn hello(server_version: &str, session: Session) -> Self {
+        Response {
+            accepted: Vec::new(),
+            depth: Vec::new(),
+            rejected: Vec::new(),
+            subscription_id: None,
+            server_version: server_version.to_string(),
+            error: None,
+            session: Some(session),
+            quotes: Vec::new(),
+            ping: None,
         }
     }
 
-    /// Build UDP socket address from the fields.
-    pub fn get_udp_addr(&self) -> Result<SocketAddr, std::net::AddrParseError> {
-        format!("{}:{}", self.address, self.port).parse()
+    /// Creates the reply to a `RETRANSMIT` carrying the recovered `quotes`.
+    pub fn retransmit(server_version: &str, quotes: Vec<Quote>) -> Self {
+        Response {
+            accepted: Vec::new(),
+            depth: Vec::new(),
+            rejected: Vec::new(),
+            subscription_id: None,
+            server_version: server_version.to_string(),
+            error: None,
+            session: None,
+            quotes,
+            ping: None,
+        }
+    }
+
+    /// Decode a frame received on a connection that also carries a TCP quote stream.
+    ///
+    /// Responses are always JSON objects with the `accepted`, `rejected` and
+    /// `server_version` fields; any other frame is a quote payload and yields `None`.
+    pub fn from_frame(bytes: &[u8]) -> Option<Self> {
+        if bytes.first() != Some(&b'{') {
+            return None;
+        }
+        serde_json::from_slice(bytes).ok()
     }
 
-    /// Build TCP socket address from the fields.
-    pub fn get_tcp_addr(&self) -> Result<SocketAddr, std::net::AddrParseError> {
-        format!("{}:{}", self.address, self.port).parse()
+    /// Returns `true` if the request was applied in full.
+    pub fn is_success(&self) -> bool {
+        self.rejected.is_empty() && self.error.is_none()
     }
 }
@@ -47,6 +47,48 @@ pub enum ParserError {
     /// Internal logic error where a requested ticker symbol could not be resolved.
     #[error("Internal Logic Error: Ticker not found: {0}")]
     TickerNotFound(String),
+
+    /// A request named a verb that the server does not understand.
+    #[error("Unknown request verb: {0}")]
+    UnknownVerb(String),
+
+    /// Protocol handshake failed (unsupported version or no common capabilities).
+    #[error("Handshake failed: {0}")]
+    Handshake(String),
+
+    /// Invalid or unreadable configuration (config file or command-line flags).
+    #[error("Configuration error: {0}")]
+    Config(String),
+
+    /// The server could not be reached or did not answer in time.
+    #[error("Server unavailable: {0}")]
+    ServerUnavailable(String),
+
+    /// The server refused the subscription; contains the rejected tickers and reasons.
+    #[error("Subscription rejected: {0}")]
+    SubscriptionRejected(String),
+
+    /// The server refused an admin request; contains the server's reason.
+    #[error("Admin request refused: {0}")]
+    AdminRefused(String),
+
+    /// A framed message announced a payload larger than the allowed maximum.
+    #[error("Frame too large: {size} bytes (max {max})")]
+    FrameTooLarge {
+        /// Announced or actual payload size in bytes.
+        size: usize,
+        /// Maximum accepted payload size in bytes.
+        max: usize,
+    },
+
+    /// The connection ended before a complete frame was received.
+    #[error("Truncated frame: expected {expected} bytes, received {received}")]
+    FrameTruncated {
+        /// Number of bytes the frame should contain.
+        expected: usize,
+        /// Number of bytes actually received before EOF.
+        received: usize,
+    },
 }
 
 impl<T> From<PoisonError<T>> for ParserError {
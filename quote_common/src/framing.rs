@@ -0,0 +1,166 @@
+//! Length-prefixed framing for the TCP command channel.
+//!
+//! Every message on the command channel is sent as a frame: a 4-byte big-endian length
+//! followed by exactly that many bytes of JSON payload. This lets a single TCP connection
+//! carry several messages and guarantees that a message split across TCP segments (or
+//! several messages coalesced into one segment) is reassembled correctly on the other side.
+//!
+//! Frames larger than [`MAX_FRAME_SIZE`] are rejected with `ParserError::FrameTooLarge`
+//! before the payload is read, and a connection that closes mid-frame yields
+//! `ParserError::FrameTruncated`.
+use std::io::{ErrorKind, Read, Write};
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::error::ParserError;
+
+/// Size of the length prefix in bytes.
+pub const LENGTH_PREFIX_SIZE: usize = 4;
+/// Maximum accepted payload size of a single frame (1 MiB).
+pub const MAX_FRAME_SIZE: usize = 1024 * 1024;
+
+/// Write `payload` to `writer` as a single length-prefixed frame.
+///
+/// Returns `ParserError::FrameTooLarge` if the payload exceeds [`MAX_FRAME_SIZE`].
+pub fn write_frame<W: Write>(writer: &mut W, payload: &[u8]) -> Result<(), ParserError> {
+    if payload.len() > MAX_FRAME_SIZE {
+        return Err(ParserError::FrameTooLarge {
+            size: payload.len(),
+            max: MAX_FRAME_SIZE,
+        });
+    }
+    let mut frame = Vec::with_capacity(LENGTH_PREFIX_SIZE + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read a single length-prefixed frame from `reader`.
+///
+/// - Returns `Ok(None)` if the peer closed the connection cleanly between frames.
+/// - Returns `ParserError::FrameTooLarge` if the announced length exceeds [`MAX_FRAME_SIZE`].
+/// - Returns `ParserError::FrameTruncated` if the stream ends in the middle of a frame.
+pub fn read_frame<R: Read>(reader: &mut R) -> Result<Option<Vec<u8>>, ParserError> {
+    let mut prefix = [0u8; LENGTH_PREFIX_SIZE];
+    let received = read_full(reader, &mut prefix)?;
+    if received == 0 {
+        return Ok(None);
+    }
+    if received < LENGTH_PREFIX_SIZE {
+        return Err(ParserError::FrameTruncated {
+            expected: LENGTH_PREFIX_SIZE,
+            received,
+        });
+    }
+
+    let size = u32::from_be_bytes(prefix) as usize;
+    if size > MAX_FRAME_SIZE {
+        return Err(ParserError::FrameTooLarge {
+            size,
+            max: MAX_FRAME_SIZE,
+        });
+    }
+
+    let mut payload = vec![0u8; size];
+    let received = read_full(reader, &mut payload)?;
+    if received < size {
+        return Err(ParserError::FrameTruncated {
+            expected: size,
+            received,
+        });
+    }
+    Ok(Some(payload))
+}
+
+/// Serialize `message` to JSON and write it as a single frame.
+pub fn write_message<W: Write, T: Serialize>(
+    writer: &mut W,
+    message: &T,
+) -> Result<(), ParserError> {
+    let payload = serde_json::to_vec(message)?;
+    write_frame(writer, &payload)
+}
+
+/// Read a single frame and deserialize its JSON payload.
+///
+/// Returns `Ok(None)` if the peer closed the connection cleanly between frames.
+pub fn read_message<R: Read, T: DeserializeOwned>(reader: &mut R) -> Result<Option<T>, ParserError> {
+    match read_frame(reader)? {
+        Some(payload) => Ok(Some(serde_json::from_slice(&payload)?)),
+        None => Ok(None),
+    }
+}
+
+/// Fill `buf` from `reader`, returning the number of bytes read before EOF.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize, ParserError> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(ParserError::Io(e)),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn frames_round_trip_back_to_back() {
+        let mut wire = Vec::new();
+        write_frame(&mut wire, b"first").unwrap();
+        write_frame(&mut wire, b"").unwrap();
+        write_frame(&mut wire, b"third").unwrap();
+        assert_eq!(&wire[..LENGTH_PREFIX_SIZE], &5u32.to_be_bytes());
+
+        let mut reader = Cursor::new(wire);
+        assert_eq!(read_frame(&mut reader).unwrap(), Some(b"first".to_vec()));
+        assert_eq!(read_frame(&mut reader).unwrap(), Some(Vec::new()));
+        assert_eq!(read_frame(&mut reader).unwrap(), Some(b"third".to_vec()));
+        assert_eq!(read_frame(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn messages_round_trip_as_json() {
+        let mut wire = Vec::new();
+        write_message(&mut wire, &vec!["AAPL", "MSFT"]).unwrap();
+        let read: Option<Vec<String>> = read_message(&mut Cursor::new(wire)).unwrap();
+        assert_eq!(read, Some(vec!["AAPL".to_string(), "MSFT".to_string()]));
+    }
+
+    #[test]
+    fn oversized_frames_are_rejected() {
+        let announced = (MAX_FRAME_SIZE as u32 + 1).to_be_bytes();
+        let result = read_frame(&mut Cursor::new(announced.to_vec()));
+        assert!(matches!(
+            result,
+            Err(ParserError::FrameTooLarge { size, max }) if size == MAX_FRAME_SIZE + 1 && max == MAX_FRAME_SIZE
+        ));
+
+        let result = write_frame(&mut Vec::new(), &vec![0u8; MAX_FRAME_SIZE + 1]);
+        assert!(matches!(result, Err(ParserError::FrameTooLarge { .. })));
+    }
+
+    #[test]
+    fn truncated_frames_are_rejected() {
+        let result = read_frame(&mut Cursor::new(vec![0u8, 0]));
+        assert!(matches!(
+            result,
+            Err(ParserError::FrameTruncated { expected: LENGTH_PREFIX_SIZE, received: 2 })
+        ));
+
+        let mut wire = Vec::new();
+        write_frame(&mut wire, b"payload").unwrap();
+        wire.truncate(wire.len() - 3);
+        let result = read_frame(&mut Cursor::new(wire));
+        assert!(matches!(result, Err(ParserError::FrameTruncated { expected: 7, received: 4 })));
+    }
+}
@@ -0,0 +1,138 @@
+//! Protocol version handshake and capability negotiation.
+//!
+//! Before subscribing, a client may send `Request::Hello` with the highest protocol
+//! version it speaks and the features it supports. The server answers with the
+//! negotiated `Session`: the lower of both versions and the intersection of both
+//! capability sets. Every later request on the same connection is served with that
+//! session, which lets the wire format evolve without breaking deployed clients.
+//!
+//! Clients that never send `HELLO` get [`Session::legacy`], which matches the behavior
+//! of servers that predate the handshake.
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use strum_macros::Display;
+
+use crate::error::ParserError;
+
+/// Highest protocol version spoken by this build.
+pub const PROTOCOL_VERSION: u16 = 1;
+/// Lowest protocol version this build still accepts in a `HELLO`.
+pub const MIN_PROTOCOL_VERSION: u16 = 1;
+
+/// Encoding of quote payloads on the data channel.
+///
+/// See `quote::QuoteCodec` for the implementations.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, Display, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Encoding {
+    /// One JSON object per quote.
+    #[default]
+    Json,
+    /// Fixed-layout binary record per quote.
+    Binary,
+}
+
+/// Optional protocol features a peer supports.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// Supported quote encodings in order of preference.
+    pub encodings: Vec<Encoding>,
+    /// Several quotes may be coalesced into one datagram.
+    pub batching: bool,
+    /// Quotes carry per-ticker sequence numbers for gap detection.
+    pub sequence_numbers: bool,
+    /// Requests are authenticated.
+    pub auth: bool,
+    /// Binary quotes use the layout with bid, ask and their sizes. Missing in `HELLO`s from
+    /// clients that predate top-of-book quotes.
+    #[serde(default)]
+    pub top_of_book: bool,
+}
+
+impl Capabilities {
+    /// Capabilities implemented by this build.
+    pub fn supported() -> Self {
+        Self {
+            encodings: vec![Encoding::Binary, Encoding::Json],
+            batching: true,
+            sequence_numbers: true,
+            auth: false,
+            top_of_book: true,
+        }
+    }
+
+    /// Features both sides support; encodings keep `self`'s order of preference.
+    pub fn intersect(&self, other: &Capabilities) -> Capabilities {
+        Capabilities {
+            encodings: self
+                .encodings
+                .iter()
+                .filter(|e| other.encodings.contains(e))
+                .copied()
+                .collect(),
+            batching: self.batching && other.batching,
+            sequence_numbers: self.sequence_numbers && other.sequence_numbers,
+            auth: self.auth && other.auth,
+            top_of_book: self.top_of_book && other.top_of_book,
+        }
+    }
+}
+
+/// Result of a successful handshake, shared by both ends of a connection.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    /// Protocol version both sides agreed on.
+    pub version: u16,
+    /// Features both sides support.
+    pub capabilities: Capabilities,
+}
+
+impl Session {
+    /// Session assumed for clients that subscribe without a `HELLO`.
+    pub fn legacy() -> Self {
+        Self {
+            version: MIN_PROTOCOL_VERSION,
+            capabilities: Capabilities {
+                encodings: vec![Encoding::Json],
+                batching: false,
+                sequence_numbers: false,
+                auth: false,
+                top_of_book: false,
+            },
+        }
+    }
+
+    /// Negotiate a session from the client's `HELLO` against this build's capabilities.
+    ///
+    /// Fails with `ParserError::Handshake` if the client's version is too old or the two
+    /// sides share no quote encoding.
+    pub fn negotiate(client_version: u16, client: &Capabilities) -> Result<Self, ParserError> {
+        if client_version < MIN_PROTOCOL_VERSION {
+            return Err(ParserError::Handshake(format!(
+                "protocol version {} is not supported (minimum {})",
+                client_version, MIN_PROTOCOL_VERSION
+            )));
+        }
+        let capabilities = client.intersect(&Capabilities::supported());
+        if capabilities.encodings.is_empty() {
+            return Err(ParserError::Handshake(format!(
+                "no common quote encoding (client offers {:?})",
+                client.encodings
+            )));
+        }
+        Ok(Self {
+            version: client_version.min(PROTOCOL_VERSION),
+            capabilities,
+        })
+    }
+
+    /// Encoding used for the quote stream: the first mutually supported one.
+    pub fn encoding(&self) -> Encoding {
+        self.capabilities
+            .encodings
+            .first()
+            .copied()
+            .unwrap_or(Encoding::Json)
+    }
+}
@@ -5,8 +5,12 @@
 //! - `error` — unified error type `ParserError` used across the workspace.
 //! - `result` — handy `Result<T, ParserError>` alias.
 //! - `tickers` — ticker symbols and parsing helpers shared by both sides.
-//! - `command` — TCP command payloads exchanged between client and server.
+//! - `command` — typed TCP requests and responses exchanged between client and server.
 //! - `net` — networking constants and small helpers.
+//! - `framing` — length-prefixed framing for messages on the TCP command channel.
+//! - `handshake` — protocol version and capability negotiation (`HELLO`).
+//! - `admin` — operator requests and replies exchanged on the server's admin port.
+//! - `book` — Level 2 order book snapshots and updates sent to depth subscribers.
 #![warn(missing_docs)]
 pub mod error;
 pub mod result;
@@ -14,7 +18,11 @@ pub mod tickers;
 pub mod command;
 pub mod net;
 pub mod quote;
+pub mod framing;
+pub mod handshake;
+pub mod admin;
+pub mod book;
 
 pub use error::ParserError;
 pub use result::Result;
-pub use command::Command;
\ No newline at end of file
+pub use command::Request;
\ No newline at end of file
@@ -4,6 +4,10 @@
 pub const COMMAND_PORT: u16 = 8080;
 /// UDP port for data streaming and pings (server <-> client).
 pub const DATA_PORT: u16 = 8081;
+/// TCP port for the admin interface (operator -> server), bound to localhost by default.
+pub const ADMIN_PORT: u16 = 8082;
+/// TCP port of the HTTP endpoint serving Prometheus metrics.
+pub const METRICS_PORT: u16 = 8083;
 
 /// Helper to format an IPv4 address with a port like "ip:port".
 pub fn addr(ip: &str, port: u16) -> String {
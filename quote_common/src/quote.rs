@@ -1,67 +1,155 @@
 //! Quote data model and JSON encoding helpers.
 //!
 //! A `Quote` is the payload sent to clients. It contains the ticker symbol, the last
-//! traded price, a synthetic volume, and a millisecond UTC timestamp. This module also
-//! provides helper methods for generating synthetic prices and for encoding quotes to JSON.
+//! trade (price and size), the top of the book (best bid and ask with their sizes), a
+//! millisecond UTC timestamp and a per-ticker sequence number assigned by the generator.
+//! This module also provides helper methods for generating synthetic quotes and the
+//! `QuoteCodec` trait with its two wire encodings:
+//!
+//! - `JsonCodec` — one JSON object per quote (~130 bytes), the original format. The
+//!   top-of-book fields were added later; consumers that ignore unknown fields keep
+//!   reading `price` and `volume` as before, and quotes from older servers decode with the
+//!   new fields set to `0`.
+//! - `BinaryCodec` — a fixed big-endian layout of 31 bytes, or 55 bytes with the
+//!   top-of-book fields when the session negotiated `top_of_book`:
+//!
+//! | offset | size | field                                                        |
+//! |--------|------|--------------------------------------------------------------|
+//! | 0      | 1    | message tag, [`BINARY_QUOTE_TAG`] or [`BINARY_L1_QUOTE_TAG`] |
+//! | 1      | 2    | symbol id (`Ticker::id`)                                     |
+//! | 3      | 8    | last trade price in 1/[`PRICE_SCALE`] units                  |
+//! | 11     | 4    | last trade volume                                            |
+//! | 15     | 8    | timestamp, ms since Unix epoch                               |
+//! | 23     | 8    | per-ticker sequence number                                   |
+//! | 31     | 8    | bid price in 1/[`PRICE_SCALE`] units (top of book only)      |
+//! | 39     | 8    | ask price in 1/[`PRICE_SCALE`] units (top of book only)      |
+//! | 47     | 4    | bid size (top of book only)                                  |
+//! | 51     | 4    | ask size (top of book only)                                  |
+//!
+//! When batching is negotiated, several quotes may share one datagram:
+//!
+//! - JSON — a JSON array of quote objects;
+//! - binary — tag [`BINARY_BATCH_TAG`] (or [`BINARY_L1_BATCH_TAG`] for top-of-book
+//!   records), a `u16` quote count, then that many quote records laid out as above but
+//!   without their leading tag byte.
+//!
+//! [`QuoteCodec::decode_batch`] accepts both single-quote and batch datagrams.
+//!
+//! Besides quotes, the data channel carries `StreamNotice` control messages, e.g. the
+//! "going away" notice a server sends before it shuts down. Notices are JSON objects
+//! tagged by `event` regardless of the stream's encoding.
 
 use rand::Rng;
 use serde::{Serialize, Deserialize};
 use crate::ParserError;
+use crate::handshake::Encoding;
 use crate::tickers::Ticker;
 use chrono::Utc;
 
+/// First byte of every binary-encoded quote.
+pub const BINARY_QUOTE_TAG: u8 = 0x01;
+/// Size of a binary-encoded quote in bytes.
+pub const BINARY_QUOTE_SIZE: usize = 31;
+/// First byte of a binary datagram carrying several quotes.
+pub const BINARY_BATCH_TAG: u8 = 0x02;
+/// Largest datagram payload the server produces; keeps datagrams within a typical
+/// 1500-byte Ethernet MTU after IP/UDP headers.
+pub const MAX_DATAGRAM_SIZE: usize = 1400;
+/// Fixed-point scale of binary prices: 1 unit = 1/10_000 of a currency unit.
+pub const PRICE_SCALE: f64 = 10_000.0;
+/// First byte of a binary quote with top-of-book fields.
+pub const BINARY_L1_QUOTE_TAG: u8 = 0x03;
+/// Size of a binary-encoded quote with top-of-book fields in bytes.
+pub const BINARY_L1_QUOTE_SIZE: usize = 55;
+/// First byte of a binary datagram carrying several quotes with top-of-book fields.
+pub const BINARY_L1_BATCH_TAG: u8 = 0x04;
+/// Price increments per currency unit; bids, asks and book levels are whole cents.
+pub const TICKS_PER_UNIT: f64 = 100.0;
+
+/// Convert `price` to whole ticks (`1 / TICKS_PER_UNIT`), rounding to the nearest one.
+pub fn to_ticks(price: f64) -> u64 {
+    (price * TICKS_PER_UNIT).round() as u64
+}
+
+/// Convert a price in ticks back to currency units.
+pub fn from_ticks(ticks: u64) -> f64 {
+    ticks as f64 / TICKS_PER_UNIT
+}
+
 /// Market quote for a single ticker symbol.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Quote {
     /// Symbol identifier (string form of `Ticker`).
     pub ticker: String,
-    /// Last traded price.
+    /// Price of the last trade.
     pub price: f64,
-    /// Synthetic trade volume associated with this tick.
+    /// Size of the last trade.
     pub volume: u32,
     /// UTC timestamp in milliseconds since Unix epoch.
     pub timestamp: u64,
+    /// Per-ticker sequence number; consecutive quotes of one ticker differ by one.
+    /// Missing in messages from servers that predate sequencing.
+    #[serde(default)]
+    pub seq: u64,
+    /// Best bid price; `0` in messages from servers that predate top-of-book quotes.
+    #[serde(default)]
+    pub bid: f64,
+    /// Best ask price; `0` in messages from servers that predate top-of-book quotes.
+    #[serde(default)]
+    pub ask: f64,
+    /// Shares bid at `bid`.
+    #[serde(default)]
+    pub bid_size: u32,
+    /// Shares offered at `ask`.
+    #[serde(default)]
+    pub ask_size: u32,
 }
 
 impl Quote {
-    /// Calculate the next synthetic price using a small random walk around `current_price`.
-    ///
-    /// The change is sampled uniformly from the range `[-1%, +1%]` and the result is
-    /// clamped to a minimum positive value to avoid non-sensical zero/negative prices.
+    /// Build a `Quote` for `ticker` around the mid price `mid`, drawing the rest from `rng`.
     ///
-    /// - current_price: last known price for the symbol.
-    /// - Returns: a new price value for the next tick.
-    pub fn next_price(current_price: f64) -> f64 {
-        let mut rng = rand::rng();
-        let change: f64 = rng.random_range(-0.01..0.01);
-        let new_price = current_price * (1.0 + change);
-        new_price.max(0.01)
-    }
+    /// The bid and ask are whole cents around `mid`. The spread is about one basis point for
+    /// liquid names (AAPL/MSFT/TSLA) and four for the others, never less than a cent, and
+    /// now and then widens. Book sizes are round lots, and the last trade takes the bid or
+    /// the ask at random. Liquid names also get larger sizes and trade volumes. The sequence
+    /// number is left at `0` for the caller to assign.
+    pub fn synthesize<R: Rng + ?Sized>(ticker: &Ticker, mid: f64, rng: &mut R) -> Quote {
+        let liquid = matches!(ticker, Ticker::AAPL | Ticker::MSFT | Ticker::TSLA);
+        let (spread_bps, max_lots) = if liquid { (1.0, 50) } else { (4.0, 10) };
 
-    /// Generate a new `Quote` for the given `ticker` using `current_price` as a base.
-    ///
-    /// Volume is synthesized based on the ticker: liquid names (AAPL/MSFT/TSLA) get a
-    /// higher baseline; others receive a smaller baseline. The price is derived from
-    /// [`Self::next_price`].
-    ///
-    /// - ticker: target symbol identifier.
-    /// - current_price: last price used as a base for the next tick.
-    /// - Returns: a fully-populated `Quote` with JSON-serializable fields.
-    pub fn generate_new(ticker: &Ticker, current_price: f64) -> Result<Quote, ParserError> {
-        let mut rng = rand::rng();
-        let volume = match ticker {
-            Ticker::AAPL | Ticker::MSFT | Ticker::TSLA => {
-                1000 + rng.random_range(0..5000) as u32
-            },
-            _ => 100 + rng.random_range(0..1000) as u32,
+        let mut spread_ticks = (mid * TICKS_PER_UNIT * spread_bps / 10_000.0).round().max(1.0);
+        if rng.random_bool(0.1) {
+            spread_ticks += rng.random_range(1.0..=spread_ticks).round();
+        }
+        let bid_ticks = (mid * TICKS_PER_UNIT - spread_ticks / 2.0).round().max(1.0);
+        let bid = bid_ticks / TICKS_PER_UNIT;
+        let ask = (bid_ticks + spread_ticks) / TICKS_PER_UNIT;
+
+        let volume = if liquid {
+            1000 + rng.random_range(0..5000) as u32
+        } else {
+            100 + rng.random_range(0..1000) as u32
         };
+        let bid_size = 100 * rng.random_range(1..=max_lots);
+        let ask_size = 100 * rng.random_range(1..=max_lots);
+        let price = if rng.random_bool(0.5) { ask } else { bid };
 
-        Ok(Quote {
+        Quote {
             ticker: ticker.to_string(),
-            price: Self::next_price(current_price),
+            price,
             volume,
             timestamp: Utc::now().timestamp_millis() as u64,
-        })
+            seq: 0,
+            bid,
+            ask,
+            bid_size,
+            ask_size,
+        }
+    }
+
+    /// Whether the quote carries top-of-book fields (quotes from older servers do not).
+    pub fn has_top_of_book(&self) -> bool {
+        self.bid > 0.0 && self.ask > 0.0
     }
 
     /// Encode the quote to JSON bytes.
@@ -70,3 +158,344 @@ impl Quote {
         Ok(json)
     }
 }
+
+/// Control message sent on the data channel in place of quotes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event")]
+pub enum StreamNotice {
+    /// The server ends the stream, e.g. because it is shutting down or the client fell too
+    /// far behind; no further quotes will follow.
+    #[serde(rename = "GOING_AWAY")]
+    GoingAway {
+        /// Human-readable reason the stream ends.
+        reason: String,
+    },
+}
+
+impl StreamNotice {
+    /// Encode the notice as a JSON datagram or frame payload.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ParserError> {
+        Ok(serde_json::to_vec(self)?)
+    }
+
+    /// Decode a notice, returning `None` for quote payloads and anything else.
+    pub fn from_slice(bytes: &[u8]) -> Option<Self> {
+        if bytes.first() != Some(&b'{') {
+            return None;
+        }
+        serde_json::from_slice(bytes).ok()
+    }
+}
+
+/// Wire encoding for quotes sent on the data channel.
+pub trait QuoteCodec: Send + Sync {
+    /// Encoding implemented by this codec.
+    fn encoding(&self) -> Encoding;
+
+    /// Encode a single quote into a datagram payload.
+    fn encode(&self, quote: &Quote) -> Result<Vec<u8>, ParserError>;
+
+    /// Decode a single quote from a datagram payload.
+    fn decode(&self, bytes: &[u8]) -> Result<Quote, ParserError>;
+
+    /// Encode several quotes into one datagram payload.
+    fn encode_batch(&self, quotes: &[Quote]) -> Result<Vec<u8>, ParserError>;
+
+    /// Decode a datagram holding either a single quote or a batch.
+    fn decode_batch(&self, bytes: &[u8]) -> Result<Vec<Quote>, ParserError>;
+}
+
+/// JSON encoding: one `serde_json` object per quote.
+pub struct JsonCodec;
+
+impl QuoteCodec for JsonCodec {
+    fn encoding(&self) -> Encoding {
+        Encoding::Json
+    }
+
+    fn encode(&self, quote: &Quote) -> Result<Vec<u8>, ParserError> {
+        quote.to_json_bytes()
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Quote, ParserError> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+
+    fn encode_batch(&self, quotes: &[Quote]) -> Result<Vec<u8>, ParserError> {
+        Ok(serde_json::to_vec(quotes)?)
+    }
+
+    fn decode_batch(&self, bytes: &[u8]) -> Result<Vec<Quote>, ParserError> {
+        match bytes.iter().find(|b| !b.is_ascii_whitespace()) {
+            Some(b'[') => Ok(serde_json::from_slice(bytes)?),
+            _ => Ok(vec![self.decode(bytes)?]),
+        }
+    }
+}
+
+/// Compact fixed-layout binary encoding (see the module docs for the layouts).
+///
+/// Decoding accepts both layouts; `top_of_book` only selects the layout written.
+pub struct BinaryCodec {
+    /// Encode quotes with the top-of-book fields.
+    pub top_of_book: bool,
+}
+
+impl BinaryCodec {
+    /// Tags of a single quote and of a batch, and the size of a tagged record.
+    fn layout(top_of_book: bool) -> (u8, u8, usize) {
+        if top_of_book {
+            (BINARY_L1_QUOTE_TAG, BINARY_L1_BATCH_TAG, BINARY_L1_QUOTE_SIZE)
+        } else {
+            (BINARY_QUOTE_TAG, BINARY_BATCH_TAG, BINARY_QUOTE_SIZE)
+        }
+    }
+
+    /// Append the untagged binary record of `quote` to `buf`.
+    fn write_record(&self, quote: &Quote, buf: &mut Vec<u8>) -> Result<(), ParserError> {
+        let ticker: Ticker = quote
+            .ticker
+            .parse()
+            .map_err(|_| ParserError::TickerNotFound(quote.ticker.clone()))?;
+        let scaled = |price: f64| ((price * PRICE_SCALE).round() as u64).to_be_bytes();
+
+        buf.extend_from_slice(&ticker.id().to_be_bytes());
+        buf.extend_from_slice(&scaled(quote.price));
+        buf.extend_from_slice(&quote.volume.to_be_bytes());
+        buf.extend_from_slice(&quote.timestamp.to_be_bytes());
+        buf.extend_from_slice(&quote.seq.to_be_bytes());
+        if self.top_of_book {
+            buf.extend_from_slice(&scaled(quote.bid));
+            buf.extend_from_slice(&scaled(quote.ask));
+            buf.extend_from_slice(&quote.bid_size.to_be_bytes());
+            buf.extend_from_slice(&quote.ask_size.to_be_bytes());
+        }
+        Ok(())
+    }
+
+    /// Parse an untagged binary record of exactly `BINARY_QUOTE_SIZE - 1` bytes, or
+    /// `BINARY_L1_QUOTE_SIZE - 1` bytes if `top_of_book` is set.
+    fn read_record(record: &[u8], top_of_book: bool) -> Result<Quote, ParserError> {
+        let u64_at = |at: usize| u64::from_be_bytes(record[at..at + 8].try_into().expect("8-byte slice"));
+        let u32_at = |at: usize| u32::from_be_bytes(record[at..at + 4].try_into().expect("4-byte slice"));
+        let id = u16::from_be_bytes([record[0], record[1]]);
+        let ticker = Ticker::from_id(id)
+            .ok_or_else(|| ParserError::TickerNotFound(format!("symbol id {}", id)))?;
+
+        let mut quote = Quote {
+            ticker: ticker.to_string(),
+            price: u64_at(2) as f64 / PRICE_SCALE,
+            volume: u32_at(10),
+            timestamp: u64_at(14),
+            seq: u64_at(22),
+            bid: 0.0,
+            ask: 0.0,
+            bid_size: 0,
+            ask_size: 0,
+        };
+        if top_of_book {
+            quote.bid = u64_at(30) as f64 / PRICE_SCALE;
+            quote.ask = u64_at(38) as f64 / PRICE_SCALE;
+            quote.bid_size = u32_at(46);
+            quote.ask_size = u32_at(50);
+        }
+        Ok(quote)
+    }
+}
+
+impl QuoteCodec for BinaryCodec {
+    fn encoding(&self) -> Encoding {
+        Encoding::Binary
+    }
+
+    fn encode(&self, quote: &Quote) -> Result<Vec<u8>, ParserError> {
+        let (tag, _, size) = Self::layout(self.top_of_book);
+        let mut buf = Vec::with_capacity(size);
+        buf.push(tag);
+        self.write_record(quote, &mut buf)?;
+        Ok(buf)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Quote, ParserError> {
+        match (bytes.first(), bytes.len()) {
+            (Some(&BINARY_QUOTE_TAG), BINARY_QUOTE_SIZE) => Self::read_record(&bytes[1..], false),
+            (Some(&BINARY_L1_QUOTE_TAG), BINARY_L1_QUOTE_SIZE) => Self::read_record(&bytes[1..], true),
+            _ => Err(ParserError::Format(format!(
+                "not a binary quote: {} bytes, tag {:#04x}",
+                bytes.len(),
+                bytes.first().copied().unwrap_or_default()
+            ))),
+        }
+    }
+
+    fn encode_batch(&self, quotes: &[Quote]) -> Result<Vec<u8>, ParserError> {
+        let count = u16::try_from(quotes.len())
+            .map_err(|_| ParserError::Format(format!("batch of {} quotes is too large", quotes.len())))?;
+        let (_, tag, size) = Self::layout(self.top_of_book);
+        let mut buf = Vec::with_capacity(3 + quotes.len() * (size - 1));
+        buf.push(tag);
+        buf.extend_from_slice(&count.to_be_bytes());
+        for quote in quotes {
+            self.write_record(quote, &mut buf)?;
+        }
+        Ok(buf)
+    }
+
+    fn decode_batch(&self, bytes: &[u8]) -> Result<Vec<Quote>, ParserError> {
+        let top_of_book = match bytes.first() {
+            Some(&BINARY_BATCH_TAG) if bytes.len() >= 3 => false,
+            Some(&BINARY_L1_BATCH_TAG) if bytes.len() >= 3 => true,
+            _ => return Ok(vec![self.decode(bytes)?]),
+        };
+        let record_size = Self::layout(top_of_book).2 - 1;
+        let count = u16::from_be_bytes([bytes[1], bytes[2]]) as usize;
+        let records = &bytes[3..];
+        if records.len() != count * record_size {
+            return Err(ParserError::Format(format!(
+                "binary batch of {} quotes has {} payload bytes",
+                count,
+                records.len()
+            )));
+        }
+        records
+            .chunks_exact(record_size)
+            .map(|record| Self::read_record(record, top_of_book))
+            .collect()
+    }
+}
+
+impl Encoding {
+    /// Codec implementing this encoding; `top_of_book` selects the binary layout written
+    /// (JSON quotes always carry the top-of-book fields).
+    pub fn codec(self, top_of_book: bool) -> &'static dyn QuoteCodec {
+        match (self, top_of_book) {
+            (Encoding::Json, _) => &JsonCodec,
+            (Encoding::Binary, false) => &BinaryCodec { top_of_book: false },
+            (Encoding::Binary, true) => &BinaryCodec { top_of_book: true },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(ticker: &str, seq: u64) -> Quote {
+        Quote {
+            ticker: ticker.to_string(),
+            price: 190.25,
+            volume: 300,
+            timestamp: 1_700_000_000_000,
+            seq,
+            bid: 190.24,
+            ask: 190.26,
+            bid_size: 800,
+            ask_size: 1200,
+        }
+    }
+
+    /// `quote` as it reads back from the layout without top-of-book fields.
+    fn without_book(quote: Quote) -> Quote {
+        Quote { bid: 0.0, ask: 0.0, bid_size: 0, ask_size: 0, ..quote }
+    }
+
+    #[test]
+    fn json_round_trips_single_quotes_and_batches() {
+        let codec = JsonCodec;
+        let single = quote("AAPL", 1);
+        assert_eq!(codec.decode(&codec.encode(&single).unwrap()).unwrap(), single);
+
+        let batch = vec![quote("AAPL", 2), quote("MSFT", 7)];
+        let encoded = codec.encode_batch(&batch).unwrap();
+        assert_eq!(encoded.first(), Some(&b'['));
+        assert_eq!(codec.decode_batch(&encoded).unwrap(), batch);
+        assert_eq!(codec.decode_batch(&codec.encode(&single).unwrap()).unwrap(), vec![single]);
+    }
+
+    #[test]
+    fn json_quotes_without_book_fields_decode_with_zeroes() {
+        let legacy = br#"{"ticker":"AAPL","price":190.25,"volume":300,"timestamp":1700000000000,"seq":1}"#;
+        assert_eq!(JsonCodec.decode(legacy).unwrap(), without_book(quote("AAPL", 1)));
+    }
+
+    #[test]
+    fn binary_quote_layout_0x01_round_trips() {
+        let codec = BinaryCodec { top_of_book: false };
+        let encoded = codec.encode(&quote("AAPL", 3)).unwrap();
+        assert_eq!(encoded[0], BINARY_QUOTE_TAG);
+        assert_eq!(encoded.len(), BINARY_QUOTE_SIZE);
+        assert_eq!(codec.decode(&encoded).unwrap(), without_book(quote("AAPL", 3)));
+    }
+
+    #[test]
+    fn binary_batch_layout_0x02_round_trips() {
+        let codec = BinaryCodec { top_of_book: false };
+        let batch = vec![quote("AAPL", 4), quote("TSLA", 5), quote("MSFT", 6)];
+        let encoded = codec.encode_batch(&batch).unwrap();
+        assert_eq!(encoded[0], BINARY_BATCH_TAG);
+        assert_eq!(encoded.len(), 3 + batch.len() * (BINARY_QUOTE_SIZE - 1));
+        let expected: Vec<Quote> = batch.into_iter().map(without_book).collect();
+        assert_eq!(codec.decode_batch(&encoded).unwrap(), expected);
+    }
+
+    #[test]
+    fn binary_quote_layout_0x03_round_trips() {
+        let codec = BinaryCodec { top_of_book: true };
+        let encoded = codec.encode(&quote("AAPL", 7)).unwrap();
+        assert_eq!(encoded[0], BINARY_L1_QUOTE_TAG);
+        assert_eq!(encoded.len(), BINARY_L1_QUOTE_SIZE);
+        assert_eq!(codec.decode(&encoded).unwrap(), quote("AAPL", 7));
+    }
+
+    #[test]
+    fn binary_batch_layout_0x04_round_trips() {
+        let codec = BinaryCodec { top_of_book: true };
+        let batch = vec![quote("AAPL", 8), quote("TSLA", 9)];
+        let encoded = codec.encode_batch(&batch).unwrap();
+        assert_eq!(encoded[0], BINARY_L1_BATCH_TAG);
+        assert_eq!(encoded.len(), 3 + batch.len() * (BINARY_L1_QUOTE_SIZE - 1));
+        assert_eq!(codec.decode_batch(&encoded).unwrap(), batch);
+    }
+
+    #[test]
+    fn binary_decoding_accepts_either_layout() {
+        let plain = BinaryCodec { top_of_book: false }.encode(&quote("AAPL", 10)).unwrap();
+        let l1 = BinaryCodec { top_of_book: true }.encode(&quote("AAPL", 11)).unwrap();
+        let decoded = BinaryCodec { top_of_book: true }.decode_batch(&plain).unwrap();
+        assert_eq!(decoded, vec![without_book(quote("AAPL", 10))]);
+        let decoded = BinaryCodec { top_of_book: false }.decode_batch(&l1).unwrap();
+        assert_eq!(decoded, vec![quote("AAPL", 11)]);
+    }
+
+    #[test]
+    fn binary_records_of_the_wrong_length_are_rejected() {
+        for top_of_book in [false, true] {
+            let codec = BinaryCodec { top_of_book };
+            let encoded = codec.encode(&quote("AAPL", 12)).unwrap();
+            assert!(matches!(codec.decode(&encoded[..encoded.len() - 1]), Err(ParserError::Format(_))));
+
+            let mut longer = encoded.clone();
+            longer.push(0);
+            assert!(matches!(codec.decode(&longer), Err(ParserError::Format(_))));
+
+            let batch = codec.encode_batch(&[quote("AAPL", 13), quote("MSFT", 14)]).unwrap();
+            assert!(matches!(codec.decode_batch(&batch[..batch.len() - 1]), Err(ParserError::Format(_))));
+        }
+
+        // A plain tag on a top-of-book record, and the other way round.
+        let mut l1 = BinaryCodec { top_of_book: true }.encode(&quote("AAPL", 15)).unwrap();
+        l1[0] = BINARY_QUOTE_TAG;
+        assert!(BinaryCodec { top_of_book: true }.decode(&l1).is_err());
+        let mut plain = BinaryCodec { top_of_book: false }.encode(&quote("AAPL", 16)).unwrap();
+        plain[0] = BINARY_L1_QUOTE_TAG;
+        assert!(BinaryCodec { top_of_book: false }.decode(&plain).is_err());
+    }
+
+    #[test]
+    fn binary_records_with_an_unknown_symbol_id_are_rejected() {
+        let codec = BinaryCodec { top_of_book: false };
+        let mut encoded = codec.encode(&quote("AAPL", 17)).unwrap();
+        encoded[1..3].copy_from_slice(&u16::MAX.to_be_bytes());
+        assert!(matches!(codec.decode(&encoded), Err(ParserError::TickerNotFound(_))));
+    }
+}
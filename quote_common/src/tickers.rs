@@ -3,7 +3,7 @@
 use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
 use std::io::BufRead;
-use strum_macros::{Display, EnumString};
+use strum_macros::{Display, EnumString, FromRepr};
 
 use crate::error::ParserError;
 
@@ -37,6 +37,10 @@ impl TickerParser for Ticker {
 }
 
 /// Set of supported ticker symbols.
+///
+/// The discriminants are the symbol ids of the binary wire format (see [`Ticker::id`]), so
+/// they are pinned explicitly: never change or reuse an existing id, and give a new ticker
+/// the next free one.
 #[allow(missing_docs)]
 #[derive(
     Debug,
@@ -49,119 +53,172 @@ impl TickerParser for Ticker {
     Hash,
     Eq,
     PartialEq,
+    FromRepr,
 )]
 #[clap(rename_all = "lower")]
 #[strum(ascii_case_insensitive)]
+#[repr(u16)]
 pub enum Ticker {
-    AAPL,
-    MSFT,
-    GOOGL,
-    AMZN,
-    NVDA,
-    META,
-    TSLA,
-    JPM,
-    JNJ,
-    V,
-    PG,
-    UNH,
-    HD,
-    DIS,
-    PYPL,
-    NFLX,
-    ADBE,
-    CRM,
-    INTC,
-    CSCO,
-    PFE,
-    ABT,
-    TMO,
-    ABBV,
-    LLY,
-    PEP,
-    COST,
-    TXN,
-    AVGO,
-    ACN,
-    QCOM,
-    DHR,
-    MDT,
-    NKE,
-    UPS,
-    RTX,
-    HON,
-    ORCL,
-    LIN,
-    AMGN,
-    LOW,
-    SBUX,
-    SPGI,
-    INTU,
-    ISRG,
-    T,
-    BMY,
-    DE,
-    PLD,
-    CI,
-    CAT,
-    GS,
-    UNP,
-    AMT,
-    AXP,
-    MS,
-    BLK,
-    GE,
-    SYK,
-    GILD,
-    MMM,
-    MO,
-    LMT,
-    FISV,
-    ADI,
-    BKNG,
-    C,
-    SO,
-    NEE,
-    ZTS,
-    TGT,
-    DUK,
-    ICE,
-    BDX,
-    PNC,
-    CMCSA,
-    SCHW,
-    MDLZ,
-    TJX,
-    USB,
-    CL,
-    EMR,
-    APD,
-    COF,
-    FDX,
-    AON,
-    WM,
-    ECL,
-    ITW,
-    VRTX,
-    D,
-    NSC,
-    PGR,
-    ETN,
-    FIS,
-    PSA,
-    KLAC,
-    MCD,
-    ADP,
-    APTV,
-    AEP,
-    MCO,
-    SHW,
-    DD,
-    ROP,
-    SLB,
-    HUM,
-    BSX,
-    NOC,
-    EW,
-    UNKNOWN,
+    AAPL = 0,
+    MSFT = 1,
+    GOOGL = 2,
+    AMZN = 3,
+    NVDA = 4,
+    META = 5,
+    TSLA = 6,
+    JPM = 7,
+    JNJ = 8,
+    V = 9,
+    PG = 10,
+    UNH = 11,
+    HD = 12,
+    DIS = 13,
+    PYPL = 14,
+    NFLX = 15,
+    ADBE = 16,
+    CRM = 17,
+    INTC = 18,
+    CSCO = 19,
+    PFE = 20,
+    ABT = 21,
+    TMO = 22,
+    ABBV = 23,
+    LLY = 24,
+    PEP = 25,
+    COST = 26,
+    TXN = 27,
+    AVGO = 28,
+    ACN = 29,
+    QCOM = 30,
+    DHR = 31,
+    MDT = 32,
+    NKE = 33,
+    UPS = 34,
+    RTX = 35,
+    HON = 36,
+    ORCL = 37,
+    LIN = 38,
+    AMGN = 39,
+    LOW = 40,
+    SBUX = 41,
+    SPGI = 42,
+    INTU = 43,
+    ISRG = 44,
+    T = 45,
+    BMY = 46,
+    DE = 47,
+    PLD = 48,
+    CI = 49,
+    CAT = 50,
+    GS = 51,
+    UNP = 52,
+    AMT = 53,
+    AXP = 54,
+    MS = 55,
+    BLK = 56,
+    GE = 57,
+    SYK = 58,
+    GILD = 59,
+    MMM = 60,
+    MO = 61,
+    LMT = 62,
+    FISV = 63,
+    ADI = 64,
+    BKNG = 65,
+    C = 66,
+    SO = 67,
+    NEE = 68,
+    ZTS = 69,
+    TGT = 70,
+    DUK = 71,
+    ICE = 72,
+    BDX = 73,
+    PNC = 74,
+    CMCSA = 75,
+    SCHW = 76,
+    MDLZ = 77,
+    TJX = 78,
+    USB = 79,
+    CL = 80,
+    EMR = 81,
+    APD = 82,
+    COF = 83,
+    FDX = 84,
+    AON = 85,
+    WM = 86,
+    ECL = 87,
+    ITW = 88,
+    VRTX = 89,
+    D = 90,
+    NSC = 91,
+    PGR = 92,
+    ETN = 93,
+    FIS = 94,
+    PSA = 95,
+    KLAC = 96,
+    MCD = 97,
+    ADP = 98,
+    APTV = 99,
+    AEP = 100,
+    MCO = 101,
+    SHW = 102,
+    DD = 103,
+    ROP = 104,
+    SLB = 105,
+    HUM = 106,
+    BSX = 107,
+    NOC = 108,
+    EW = 109,
+    UNKNOWN = 110,
+}
+
+impl Ticker {
+    /// Every real ticker symbol, in declaration order (everything except `UNKNOWN`).
+    pub fn all() -> Vec<Self> {
+        Self::value_variants()
+            .iter()
+            .filter(|ticker| **ticker != Self::UNKNOWN)
+            .cloned()
+            .collect()
+    }
+
+    /// Stable numeric symbol id used by compact wire encodings: the variant's explicit
+    /// discriminant.
+    pub fn id(&self) -> u16 {
+        self.clone() as u16
+    }
+
+    /// Resolve a symbol id produced by [`Ticker::id`].
+    pub fn from_id(id: u16) -> Option<Self> {
+        Self::from_repr(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symbol_ids_are_pinned() {
+        let pinned = [
+            (Ticker::AAPL, 0),
+            (Ticker::MSFT, 1),
+            (Ticker::TSLA, 6),
+            (Ticker::NFLX, 15),
+            (Ticker::EW, 109),
+            (Ticker::UNKNOWN, 110),
+        ];
+        for (ticker, id) in pinned {
+            assert_eq!(ticker.id(), id, "{}", ticker);
+            assert_eq!(Ticker::from_id(id), Some(ticker));
+        }
+        assert_eq!(Ticker::from_id(111), None);
+    }
+
+    #[test]
+    fn every_symbol_id_resolves_back_to_its_ticker() {
+        for ticker in Ticker::all() {
+            assert_eq!(Ticker::from_id(ticker.id()), Some(ticker));
+        }
+    }
 }
@@ -0,0 +1,150 @@
+//! Fan-out throughput: thread per client versus the `StreamPool`.
+//!
+//! Every client is sent the same number of quotes, routed exactly as the generator does it
+//! (one delivery per client and quote), encoded as JSON and sent as UDP datagrams to a
+//! local socket that is never read. Throughput is the number of delivered quotes divided by
+//! the time from the first routed quote until every stream has sent its going-away notice
+//! and stopped.
+//!
+//! - `thread-per-client` — the previous design: one channel and one thread per client.
+//! - `stream-pool` — the current design: one bounded queue per client, drained by a fixed
+//!   set of pool workers. Queues are sized so that nothing is dropped.
+//!
+//! Run with `cargo bench -p quote_server`; pass client counts to override the defaults,
+//! e.g. `cargo bench -p quote_server -- 500 20000`.
+
+use crossbeam_channel::unbounded;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use quote_common::handshake::{Encoding, Session};
+use quote_common::quote::Quote;
+use quote_common::tickers::Ticker;
+use quote_server::model::client_stream::{ClientStream, QuoteSink, StreamOptions};
+use quote_server::model::quote_generator::QuoteEvent;
+use quote_server::model::stream_pool::StreamPool;
+use quote_server::model::subscriber_queue::{
+    Delivery, OverflowPolicy, QueueConfig, Subscriber, SubscriberQueue,
+};
+use std::net::UdpSocket;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Client counts measured when none are given on the command line.
+const DEFAULT_CLIENTS: [usize; 3] = [100, 1_000, 10_000];
+/// Quotes delivered per run, spread evenly over the clients.
+const QUOTES_PER_RUN: usize = 200_000;
+/// Seed of the benchmark quotes, so every run sends the same payloads.
+const SEED: u64 = 42;
+
+fn main() {
+    let clients: Vec<usize> = std::env::args().skip(1).filter_map(|arg| arg.parse().ok()).collect();
+    let clients = if clients.is_empty() { DEFAULT_CLIENTS.to_vec() } else { clients };
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+
+    let sink_socket = UdpSocket::bind("127.0.0.1:0").expect("bind sink socket");
+    let target_addr = sink_socket.local_addr().expect("sink address");
+    let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").expect("bind send socket"));
+    let options = StreamOptions {
+        session: Session::legacy(),
+        encoding: Encoding::Json,
+        max_batch_delay: Duration::ZERO,
+        queue: QueueConfig { capacity: QUOTES_PER_RUN, policy: OverflowPolicy::DropOldest },
+        conflation: None,
+    };
+
+    println!("{:>8} {:>8} {:>20} {:>20}", "clients", "quotes", "thread-per-client", "stream-pool");
+    for &count in &clients {
+        let per_client = (QUOTES_PER_RUN / count).max(1);
+        let mut rng = StdRng::seed_from_u64(SEED);
+        let quotes: Vec<Quote> = (1..=per_client as u64)
+            .map(|seq| {
+                let mut quote = Quote::synthesize(&Ticker::AAPL, 100.0, &mut rng);
+                quote.seq = seq;
+                quote
+            })
+            .collect();
+        let stream = || ClientStream::new(QuoteSink::Udp(Arc::clone(&socket)), target_addr, &options);
+
+        let baseline = thread_per_client(count, &quotes, stream);
+        let pooled = stream_pool(count, workers, &quotes, options.queue, stream);
+        let delivered = (count * per_client) as f64;
+        println!(
+            "{:>8} {:>8} {:>16.0} q/s {:>16.0} q/s",
+            count,
+            count * per_client,
+            delivered / baseline.as_secs_f64(),
+            delivered / pooled.as_secs_f64()
+        );
+    }
+    drop(sink_socket);
+}
+
+/// One channel and one thread per client, as before the stream pool.
+fn thread_per_client(count: usize, quotes: &[Quote], stream: impl Fn() -> ClientStream) -> Duration {
+    let (senders, threads): (Vec<_>, Vec<_>) = (0..count)
+        .map(|_| {
+            let (events_tx, events_rx) = unbounded::<QuoteEvent>();
+            let mut stream = stream();
+            let thread = thread::spawn(move || {
+                while let Ok(event) = events_rx.recv() {
+                    match event {
+                        QuoteEvent::Quote(quote) => stream.push(quote).expect("send quote"),
+                        QuoteEvent::Book(message) => stream.push_book(&message).expect("send book"),
+                        QuoteEvent::Shutdown => {
+                            stream.going_away("benchmark finished").expect("send notice");
+                            break;
+                        }
+                    }
+                }
+            });
+            (events_tx, thread)
+        })
+        .unzip();
+
+    let started = Instant::now();
+    for quote in quotes {
+        for events_tx in &senders {
+            events_tx.send(QuoteEvent::Quote(quote.clone())).expect("route quote");
+        }
+    }
+    for events_tx in &senders {
+        events_tx.send(QuoteEvent::Shutdown).expect("route shutdown");
+    }
+    for thread in threads {
+        thread.join().expect("client thread");
+    }
+    started.elapsed()
+}
+
+/// Clients spread over a fixed number of pool workers.
+fn stream_pool(
+    count: usize,
+    workers: usize,
+    quotes: &[Quote],
+    queue: QueueConfig,
+    stream: impl Fn() -> ClientStream,
+) -> Duration {
+    let (closed_tx, _closed_rx) = unbounded();
+    let pool = StreamPool::start(workers, closed_tx);
+    let subscribers: Vec<_> = (0..count as u64)
+        .map(|id| {
+            let queue = Arc::new(SubscriberQueue::new(queue));
+            pool.open(id, stream(), Arc::clone(&queue)).expect("open stream");
+            Subscriber::new(id, queue, pool.ready_tx(id))
+        })
+        .collect();
+
+    let started = Instant::now();
+    for quote in quotes {
+        for subscriber in &subscribers {
+            let delivery = subscriber.deliver(QuoteEvent::Quote(quote.clone()));
+            assert_eq!(delivery, Delivery::Queued, "route quote");
+        }
+    }
+    for subscriber in &subscribers {
+        subscriber.deliver(QuoteEvent::Shutdown);
+    }
+    pool.shutdown();
+    started.elapsed()
+}
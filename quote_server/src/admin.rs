@@ -0,0 +1,143 @@
+//! Admin interface for operators.
+//!
+//! `AdminListener` accepts TCP connections on the admin address (localhost by default) and
+//! forwards every framed `AdminRequest` to the main loop as an `IncomingAdmin`. The main
+//! loop answers it with `answer`, which reads and changes the registry, the generator and
+//! the ping monitor it owns, so admin requests never race with client commands.
+use crate::receiver::REPLY_TIMEOUT;
+use crossbeam_channel::{Sender, bounded};
+use log::{error, info, warn};
+use quote_common::ParserError;
+use quote_common::admin::{AdminRequest, AdminResponse};
+use quote_common::framing::{read_frame, write_message};
+use quote_common::tickers::Ticker;
+use quote_server::model::ping_monitor::PingMonitor;
+use quote_server::model::quote_generator::QuoteGenerator;
+use quote_server::model::stream_registry::StreamRegistry;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Mutex;
+use std::thread;
+
+/// Reason sent to a client whose stream an operator ended.
+const KICK_REASON: &str = "disconnected by the server operator";
+
+/// A decoded admin request forwarded to the main loop.
+pub struct IncomingAdmin {
+    /// The request as sent by the operator.
+    pub request: AdminRequest,
+    /// Channel on which the main loop sends the reply.
+    pub reply_tx: Sender<AdminResponse>,
+}
+
+/// TCP listener for admin requests.
+pub struct AdminListener {
+    socket: TcpListener,
+}
+
+impl AdminListener {
+    /// Bind the admin listener to `bind_addr`, warning if it is reachable from other hosts.
+    pub fn new(bind_addr: SocketAddr) -> Result<Self, ParserError> {
+        if !bind_addr.ip().is_loopback() {
+            warn!("Admin interface on {} is reachable from other hosts", bind_addr);
+        }
+        let socket = TcpListener::bind(bind_addr)?;
+        Ok(Self { socket })
+    }
+
+    /// Blocking loop that serves every admin connection on its own thread.
+    pub fn serve(self, tx: Sender<IncomingAdmin>) -> Result<(), ParserError> {
+        info!("Admin TCP server is started on {}", self.socket.local_addr()?);
+        for stream in self.socket.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let tx = tx.clone();
+                    thread::spawn(move || {
+                        let peer = stream.peer_addr().ok();
+                        if let Err(e) = Self::serve_connection(stream, &tx) {
+                            error!("Failed to process admin request from {:?}: {}", peer, e);
+                        }
+                    });
+                }
+                Err(e) => error!("Admin connection error: {}", e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Answer framed requests on one connection until the operator disconnects.
+    fn serve_connection(mut stream: TcpStream, tx: &Sender<IncomingAdmin>) -> Result<(), ParserError> {
+        while let Some(frame) = read_frame(&mut stream)? {
+            let response = match serde_json::from_slice::<AdminRequest>(&frame) {
+                Ok(request) => {
+                    info!("Received admin request {:?}", request);
+                    let (reply_tx, reply_rx) = bounded::<AdminResponse>(1);
+                    tx.send(IncomingAdmin { request, reply_tx })
+                        .map_err(|_| ParserError::ChannelSend("server is shutting down".to_string()))?;
+                    reply_rx
+                        .recv_timeout(REPLY_TIMEOUT)
+                        .map_err(|e| ParserError::ChannelRecv(e.to_string()))?
+                }
+                Err(e) => AdminResponse::Error { message: e.to_string() },
+            };
+            write_message(&mut stream, &response)?;
+        }
+        Ok(())
+    }
+}
+
+/// Apply `request` to the server's state and build the reply.
+pub fn answer(
+    request: AdminRequest,
+    registry: &mut StreamRegistry,
+    generator: &QuoteGenerator,
+    ping_monitor: &Mutex<PingMonitor>,
+    tickers: &[Ticker],
+) -> AdminResponse {
+    apply(request, registry, generator, ping_monitor, tickers)
+        .unwrap_or_else(|e| {
+            let message = match e {
+                ParserError::AdminRefused(reason) => reason,
+                other => other.to_string(),
+            };
+            AdminResponse::Error { message }
+        })
+}
+
+fn apply(
+    request: AdminRequest,
+    registry: &mut StreamRegistry,
+    generator: &QuoteGenerator,
+    ping_monitor: &Mutex<PingMonitor>,
+    tickers: &[Ticker],
+) -> Result<AdminResponse, ParserError> {
+    match request {
+        AdminRequest::ListClients => {
+            let monitor = ping_monitor.lock()?;
+            Ok(AdminResponse::Clients { clients: registry.clients(|addr| monitor.last_ping(addr)) })
+        }
+        AdminRequest::Kick { client } => {
+            let subscription_id = registry
+                .kick(client, KICK_REASON)
+                .ok_or_else(|| ParserError::AdminRefused(format!("no active subscription for {}", client)))?;
+            info!("Stream for {} kicked by operator", client);
+            Ok(AdminResponse::Done { message: format!("kicked {} (subscription #{})", client, subscription_id) })
+        }
+        AdminRequest::PauseTicker { ticker } => set_paused(generator, tickers, ticker, true),
+        AdminRequest::ResumeTicker { ticker } => set_paused(generator, tickers, ticker, false),
+        AdminRequest::Stats => Ok(AdminResponse::Stats { stats: generator.stats()? }),
+    }
+}
+
+fn set_paused(
+    generator: &QuoteGenerator,
+    tickers: &[Ticker],
+    ticker: Ticker,
+    paused: bool,
+) -> Result<AdminResponse, ParserError> {
+    if !tickers.contains(&ticker) {
+        return Err(ParserError::AdminRefused(format!("{} is not generated by this server", ticker)));
+    }
+    generator.set_paused(&ticker, paused)?;
+    let action = if paused { "paused" } else { "resumed" };
+    Ok(AdminResponse::Done { message: format!("{} {}", action, ticker) })
+}
@@ -0,0 +1,412 @@
+//! Server configuration: command-line flags and an optional config file.
+//!
+//! Settings are resolved in three layers, later ones winning:
+//!
+//! 1. built-in defaults (the historical constants: ports `8080`/`8081`, the admin port
+//!    `8082` on localhost, metrics on port `8083`, a 500 ms tick,
+//!    a 5 s ping timeout with a 5 s grace period for the first ping, every supported ticker
+//!    moving by a uniform ±1% walk, one stream worker per CPU, 1024-quote client queues that
+//!    drop the oldest quote when full, 10-level order books with a snapshot every 5 s);
+//! 2. a TOML or JSON file passed with `--config` (format chosen by the file extension);
+//! 3. individual command-line flags.
+//!
+//! Each ticker starts from its explicit price under `prices` (or `--price`), else from its
+//! reference price, else from `initial_price`. Reference prices are read from the CSV file
+//! named by `reference_prices`; without one, the `reference_prices.csv` built into the
+//! server is used.
+//!
+//! Example `server.toml`:
+//! ```toml
+//! command_addr = "0.0.0.0:9080"
+//! data_addr = "0.0.0.0:9081"
+//! admin_addr = "127.0.0.1:9082"
+//! metrics_addr = "0.0.0.0:9083"
+//! tick_interval_ms = 250
+//! ping_timeout_secs = 10
+//! ping_grace_secs = 10
+//! stream_workers = 4
+//! queue_capacity = 256
+//! overflow_policy = "conflate"
+//! tickers = ["AAPL", "MSFT", "NVDA"]
+//! reference_prices = "prices.csv"
+//! initial_price = 100.0
+//! seed = 42
+//! book_levels = 5
+//! book_snapshot_interval_ms = 2000
+//!
+//! [prices]
+//! AAPL = 190.5
+//! NVDA = 120.0
+//!
+//! [price_model]
+//! kind = "merton"
+//! volatility = 0.25
+//!
+//! [price_model.tickers.NVDA]
+//! drift = 0.1
+//! volatility = 0.5
+//! ```
+use quote_server::model::order_book::BookConfig;
+use quote_server::model::price_model::{PriceModelConfig, PriceModelKind};
+use quote_server::model::quote_generator::GeneratorConfig;
+use quote_server::model::subscriber_queue::{OverflowPolicy, QueueConfig};
+use clap::Parser;
+use quote_common::ParserError;
+use quote_common::book::MAX_BOOK_LEVELS;
+use quote_common::net::{ADMIN_PORT, COMMAND_PORT, DATA_PORT, METRICS_PORT};
+use quote_common::tickers::Ticker;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// Reference prices used when no `reference_prices` file is configured.
+const REFERENCE_PRICES: &str = include_str!("../reference_prices.csv");
+
+/// Parsed command-line arguments of the server.
+///
+/// Every flag is optional; unset flags fall back to the config file and then to defaults.
+#[derive(Debug, Parser)]
+#[command(version, about, long_about = None)]
+pub struct ServerArgs {
+    /// Path to a TOML or JSON config file.
+    #[clap(long)]
+    pub config: Option<PathBuf>,
+
+    /// Address of the TCP command listener, e.g. `0.0.0.0:8080`.
+    #[clap(long)]
+    pub command_addr: Option<SocketAddr>,
+
+    /// Address of the UDP socket used for quotes and pings, e.g. `0.0.0.0:8081`.
+    #[clap(long)]
+    pub data_addr: Option<SocketAddr>,
+
+    /// Address of the TCP admin listener, e.g. `127.0.0.1:8082`. Anyone who can reach it
+    /// can kick clients, so keep it on a loopback or otherwise private address.
+    #[clap(long)]
+    pub admin_addr: Option<SocketAddr>,
+
+    /// Address of the HTTP listener serving Prometheus metrics at `/metrics`.
+    #[clap(long)]
+    pub metrics_addr: Option<SocketAddr>,
+
+    /// Interval between generated quote ticks, in milliseconds.
+    #[clap(long)]
+    pub tick_interval_ms: Option<u64>,
+
+    /// Seconds without a PING after which a UDP subscription is dropped.
+    #[clap(long)]
+    pub ping_timeout_secs: Option<u64>,
+
+    /// Extra seconds a new UDP subscriber has to send its first PING.
+    #[clap(long)]
+    pub ping_grace_secs: Option<u64>,
+
+    /// Tickers to generate quotes for, separated by commas; every supported ticker by default.
+    #[clap(long, value_delimiter = ',', ignore_case = true)]
+    pub tickers: Option<Vec<Ticker>>,
+
+    /// Number of worker threads that send quotes to clients.
+    #[clap(long)]
+    pub stream_workers: Option<usize>,
+
+    /// Most quotes queued for one client before the overflow policy applies.
+    #[clap(long)]
+    pub queue_capacity: Option<usize>,
+
+    /// What to do when a client's queue is full.
+    #[clap(long, value_enum)]
+    pub overflow_policy: Option<OverflowPolicy>,
+
+    /// CSV file of `TICKER,PRICE` reference prices the tickers start from.
+    #[clap(long)]
+    pub reference_prices: Option<PathBuf>,
+
+    /// Starting price for tickers without an explicit or reference price.
+    #[clap(long)]
+    pub initial_price: Option<f64>,
+
+    /// Starting price of a single ticker as `TICKER=PRICE`; may be repeated.
+    #[clap(long = "price", value_parser = parse_price)]
+    pub prices: Vec<(Ticker, f64)>,
+
+    /// Price process of the generated quotes; its parameters are set in the config file.
+    #[clap(long, value_enum)]
+    pub price_model: Option<PriceModelKind>,
+
+    /// Seed of the generated price paths; the same seed and settings replay the same prices.
+    #[clap(long)]
+    pub seed: Option<u64>,
+
+    /// Price levels per side of the simulated order books.
+    #[clap(long)]
+    pub book_levels: Option<usize>,
+
+    /// Interval between full order book snapshots, in milliseconds.
+    #[clap(long)]
+    pub book_snapshot_interval_ms: Option<u64>,
+}
+
+/// Fully resolved server settings.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Address of the TCP command listener.
+    pub command_addr: SocketAddr,
+    /// Address of the UDP socket used for quotes and pings.
+    pub data_addr: SocketAddr,
+    /// Address of the TCP admin listener.
+    pub admin_addr: SocketAddr,
+    /// Address of the HTTP listener serving Prometheus metrics.
+    pub metrics_addr: SocketAddr,
+    /// Interval between generated quote ticks, in milliseconds.
+    pub tick_interval_ms: u64,
+    /// Seconds without a PING after which a UDP subscription is dropped.
+    pub ping_timeout_secs: u64,
+    /// Extra seconds a new UDP subscriber has to send its first PING.
+    pub ping_grace_secs: u64,
+    /// Number of worker threads that send quotes to clients.
+    pub stream_workers: usize,
+    /// Most quotes queued for one client before the overflow policy applies.
+    pub queue_capacity: usize,
+    /// What to do when a client's queue is full.
+    pub overflow_policy: OverflowPolicy,
+    /// Tickers to generate quotes for.
+    pub tickers: Vec<Ticker>,
+    /// Reference prices file; the built-in `reference_prices.csv` if not set.
+    pub reference_prices: Option<PathBuf>,
+    /// Starting price for tickers without an explicit or reference price.
+    pub initial_price: f64,
+    /// Per-ticker starting prices; after loading, every generated ticker with a reference
+    /// price has an entry.
+    pub prices: HashMap<Ticker, f64>,
+    /// Price process and its parameters.
+    pub price_model: PriceModelConfig,
+    /// Seed of the generated price paths; a random (logged) seed if not set.
+    pub seed: Option<u64>,
+    /// Price levels per side of the simulated order books.
+    pub book_levels: usize,
+    /// Interval between full order book snapshots, in milliseconds.
+    pub book_snapshot_interval_ms: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        let any = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+        Self {
+            command_addr: SocketAddr::new(any, COMMAND_PORT),
+            data_addr: SocketAddr::new(any, DATA_PORT),
+            admin_addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), ADMIN_PORT),
+            metrics_addr: SocketAddr::new(any, METRICS_PORT),
+            tick_interval_ms: 500,
+            ping_timeout_secs: 5,
+            ping_grace_secs: 5,
+            stream_workers: thread::available_parallelism().map_or(1, |n| n.get()),
+            queue_capacity: 1024,
+            overflow_policy: OverflowPolicy::default(),
+            tickers: Ticker::all(),
+            reference_prices: None,
+            initial_price: 100.0,
+            prices: HashMap::new(),
+            price_model: PriceModelConfig::default(),
+            seed: None,
+            book_levels: 10,
+            book_snapshot_interval_ms: 5000,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Resolve the configuration from `args`: defaults, then the config file, then flags.
+    ///
+    /// Returns `ParserError::Config` if the file cannot be read or parsed, or if the
+    /// resulting settings are invalid.
+    pub fn load(args: &ServerArgs) -> Result<Self, ParserError> {
+        let mut config = match &args.config {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+
+        if let Some(addr) = args.command_addr {
+            config.command_addr = addr;
+        }
+        if let Some(addr) = args.data_addr {
+            config.data_addr = addr;
+        }
+        if let Some(addr) = args.admin_addr {
+            config.admin_addr = addr;
+        }
+        if let Some(addr) = args.metrics_addr {
+            config.metrics_addr = addr;
+        }
+        if let Some(interval) = args.tick_interval_ms {
+            config.tick_interval_ms = interval;
+        }
+        if let Some(timeout) = args.ping_timeout_secs {
+            config.ping_timeout_secs = timeout;
+        }
+        if let Some(grace) = args.ping_grace_secs {
+            config.ping_grace_secs = grace;
+        }
+        if let Some(workers) = args.stream_workers {
+            config.stream_workers = workers;
+        }
+        if let Some(capacity) = args.queue_capacity {
+            config.queue_capacity = capacity;
+        }
+        if let Some(policy) = args.overflow_policy {
+            config.overflow_policy = policy;
+        }
+        if let Some(tickers) = &args.tickers {
+            config.tickers = tickers.clone();
+        }
+        if let Some(path) = &args.reference_prices {
+            config.reference_prices = Some(path.clone());
+        }
+        if let Some(price) = args.initial_price {
+            config.initial_price = price;
+        }
+        config.prices.extend(args.prices.iter().cloned());
+        let reference = match &config.reference_prices {
+            Some(path) => {
+                let text = fs::read_to_string(path)
+                    .map_err(|e| ParserError::Config(format!("cannot read {}: {}", path.display(), e)))?;
+                parse_reference_prices(&text)
+                    .map_err(|e| ParserError::Config(format!("{}: {}", path.display(), e)))?
+            }
+            None => parse_reference_prices(REFERENCE_PRICES)
+                .map_err(|e| ParserError::Config(format!("built-in reference prices: {}", e)))?,
+        };
+        for ticker in &config.tickers {
+            if let Some(&price) = reference.get(ticker) {
+                config.prices.entry(ticker.clone()).or_insert(price);
+            }
+        }
+        if let Some(kind) = args.price_model {
+            config.price_model.kind = kind;
+        }
+        if let Some(seed) = args.seed {
+            config.seed = Some(seed);
+        }
+        if let Some(levels) = args.book_levels {
+            config.book_levels = levels;
+        }
+        if let Some(interval) = args.book_snapshot_interval_ms {
+            config.book_snapshot_interval_ms = interval;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Read a TOML (`.toml`) or JSON (`.json`) config file.
+    fn from_file(path: &Path) -> Result<Self, ParserError> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| ParserError::Config(format!("cannot read {}: {}", path.display(), e)))?;
+        let parsed = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(&text).map_err(|e| e.to_string()),
+            Some("json") => serde_json::from_str(&text).map_err(|e| e.to_string()),
+            _ => Err("expected a .toml or .json file".to_string()),
+        };
+        parsed.map_err(|e| ParserError::Config(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Reject settings the server cannot run with.
+    fn validate(&self) -> Result<(), ParserError> {
+        if self.tickers.is_empty() {
+            return Err(ParserError::Config("at least one ticker is required".to_string()));
+        }
+        if self.tickers.contains(&Ticker::UNKNOWN) {
+            return Err(ParserError::Config("UNKNOWN is not a valid ticker".to_string()));
+        }
+        if self.tick_interval_ms == 0 {
+            return Err(ParserError::Config("tick_interval_ms must be positive".to_string()));
+        }
+        if self.ping_timeout_secs == 0 {
+            return Err(ParserError::Config("ping_timeout_secs must be positive".to_string()));
+        }
+        if self.stream_workers == 0 {
+            return Err(ParserError::Config("stream_workers must be positive".to_string()));
+        }
+        if self.queue_capacity == 0 {
+            return Err(ParserError::Config("queue_capacity must be positive".to_string()));
+        }
+        if self.book_levels == 0 || self.book_levels > MAX_BOOK_LEVELS {
+            return Err(ParserError::Config(format!("book_levels must be between 1 and {}", MAX_BOOK_LEVELS)));
+        }
+        if self.book_snapshot_interval_ms == 0 {
+            return Err(ParserError::Config("book_snapshot_interval_ms must be positive".to_string()));
+        }
+        let mut prices = std::iter::once(self.initial_price).chain(self.prices.values().copied());
+        if prices.any(|price| !price.is_finite() || price <= 0.0) {
+            return Err(ParserError::Config("starting prices must be positive".to_string()));
+        }
+        self.price_model.validate()
+    }
+
+    /// Bound and overflow policy of every client's queue.
+    pub fn queue(&self) -> QueueConfig {
+        QueueConfig {
+            capacity: self.queue_capacity,
+            policy: self.overflow_policy,
+        }
+    }
+
+    /// Settings of the quote generator.
+    pub fn generator(&self) -> GeneratorConfig {
+        let initial_prices = self
+            .tickers
+            .iter()
+            .map(|ticker| {
+                let price = self.prices.get(ticker).copied().unwrap_or(self.initial_price);
+                (ticker.clone(), price)
+            })
+            .collect();
+        GeneratorConfig {
+            tickers: self.tickers.clone(),
+            initial_prices,
+            tick_interval: Duration::from_millis(self.tick_interval_ms),
+            price_model: self.price_model.clone(),
+            seed: self.seed,
+            book: BookConfig {
+                levels: self.book_levels,
+                snapshot_interval: Duration::from_millis(self.book_snapshot_interval_ms),
+            },
+        }
+    }
+}
+
+/// Parse a reference prices file: one `TICKER,PRICE` pair per line, with an optional
+/// `ticker,price` header, blank lines and `#` comments.
+fn parse_reference_prices(text: &str) -> Result<HashMap<Ticker, f64>, String> {
+    let mut prices = HashMap::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.eq_ignore_ascii_case("ticker,price") {
+            continue;
+        }
+        let (ticker, price) = line
+            .split_once(',')
+            .ok_or_else(|| format!("line {}: expected TICKER,PRICE, got `{}`", number + 1, line))?;
+        let ticker = ticker.trim().parse::<Ticker>().map_err(|e| format!("line {}: {}: {}", number + 1, ticker, e))?;
+        let price = price.trim().parse::<f64>().map_err(|e| format!("line {}: {}: {}", number + 1, price, e))?;
+        if ticker == Ticker::UNKNOWN || !price.is_finite() || price <= 0.0 {
+            return Err(format!("line {}: invalid reference price `{}`", number + 1, line));
+        }
+        prices.insert(ticker, price);
+    }
+    Ok(prices)
+}
+
+/// Parse a `TICKER=PRICE` flag value.
+fn parse_price(value: &str) -> Result<(Ticker, f64), String> {
+    let (ticker, price) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected TICKER=PRICE, got `{}`", value))?;
+    let ticker = ticker.trim().parse::<Ticker>().map_err(|e| format!("{}: {}", ticker, e))?;
+    let price = price.trim().parse::<f64>().map_err(|e| format!("{}: {}", price, e))?;
+    Ok((ticker, price))
+}
@@ -0,0 +1,10 @@
+//! Quote server building blocks.
+//!
+//! The `quote_server` binary wires these together; they are exposed as a library so that
+//! benchmarks can drive the quote generator and the client stream pool directly.
+//!
+//! - `model` — the generator, client streams and the state they share.
+//! - `metrics` — process-wide counters and gauges rendered in the Prometheus text format.
+#![warn(missing_docs)]
+pub mod metrics;
+pub mod model;
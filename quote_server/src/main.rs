@@ -3,108 +3,107 @@
 //! This binary listens on a UDP socket and streams quote updates to clients that send a
 //! subscription command. Internally, it wires together three main building blocks:
 //!
-//! - `QuoteGenerator` — produces quote events (`QuoteEvent`) and broadcasts them to all
-//!   subscribed clients via `crossbeam_channel` senders.
+//! - `QuoteGenerator` — produces quote events (`QuoteEvent`) and routes each one only to
+//!   the clients subscribed to its ticker via `crossbeam_channel` senders.
 //! - `QuoteReceiver` — listens for incoming UDP datagrams with client commands and parses
 //!   them into a command structure (e.g., a subscription with requested tickers) along with the
 //!   sender's `SocketAddr`.
-//! - Per‑client stream task — a lightweight thread created for each client to filter quotes
-//!   by the client's requested tickers and send matching quotes back to that client's address.
+//! - `QuoteHistory` — records the last quotes of every ticker so that clients can request
+//!   retransmission of quotes lost on the UDP data channel.
+//! - `StreamRegistry` — owns the active client streams and applies subscribe, unsubscribe
+//!   and ticker-change commands to them.
+//! - `AdminListener` — accepts operator requests (`quote_admin`) on a separate, by default
+//!   localhost-only, TCP port: list clients, kick a client, pause or resume a ticker and
+//!   read generator stats. They are answered by the main loop like client commands.
+//! - `MetricsListener` — serves the process-wide `Metrics` (clients, commands, quotes,
+//!   sends, pings, queue depths) in the Prometheus text format over HTTP.
+//! - `StreamPool` — a fixed set of worker threads (`--stream-workers`) that own the
+//!   per-client `ClientStream`s, encode the quotes routed to them and send them to each
+//!   client's address. The thread count does not grow with the number of clients.
 //!
 //! Concurrency and shutdown:
 //! - Crossbeam `select!` is used to multiplex incoming quotes and shutdown signals.
-//! - Each client stream owns a `shutdown_rx` that is triggered either by a keep‑alive timeout
-//!   (detected by `QuoteReceiver`) or by a global `QuoteEvent::Shutdown` broadcast from the
-//!   generator when the application is terminating.
+//! - Every queue is bounded: commands into the main loop and the generator block their
+//!   sender when full, and each client's quotes wait in a `SubscriberQueue` whose
+//!   `OverflowPolicy` (`--queue-capacity`, `--overflow-policy`) decides what a slow client
+//!   loses. Dropped quotes are logged per client.
+//! - A client stream is closed by a keep‑alive timeout (detected by `PingMonitor`, which
+//!   starts watching a UDP subscriber as soon as it subscribes), by the client, or by the
+//!   global `QuoteEvent::Shutdown` broadcast from the generator when the application is
+//!   terminating.
+//! - On SIGINT/SIGTERM the main loop stops taking commands, the generator broadcasts
+//!   `QuoteEvent::Shutdown`, every stream sends its client a "going away" notice, and the
+//!   server stops the stream workers and exits with code 0.
 //! - Any I/O or channel receive error is surfaced as `ParserError` and logged; the specific
 //!   client stream exits gracefully without impacting other clients.
 //!
+//! Configuration:
+//! - `ServerArgs` flags and an optional TOML/JSON file (`--config`) set the bind addresses,
+//!   tick interval, ping timeout, ticker universe and starting prices; see `config`.
+//!   Flags override the file, so several differently configured servers can share a host.
+//!
 //! Network protocol (high‑level):
-//! - Bind address: `0.0.0.0:8080` (see `BIND_ADDRESS`).
-//! - Client sends a subscription command (header like `J_QUOTE`) with a list of tickers.
-//! - Server spawns a stream thread for that client and starts sending JSON‑encoded quote
-//!   payloads to the client's `SocketAddr`.
+//! - Bind addresses: `0.0.0.0:8080` for commands and `0.0.0.0:8081` for data by default.
+//! - Client may open with a `HELLO` to negotiate the protocol version and capabilities; the
+//!   resulting session is stored with the client's stream.
+//! - Client sends a typed `Request` (e.g., `Request::Subscribe`, header `J_QUOTE`) with a list
+//!   of tickers. Unknown verbs are answered with an error response.
+//! - Server validates the tickers, answers with a `Response` (accepted/rejected tickers and
+//!   the assigned subscription id) on the same TCP connection, then hands a stream for that
+//!   client to the pool and starts sending JSON‑encoded quote payloads to its `SocketAddr`.
+//! - With the `tcp` transport, quotes are written as frames on the command connection
+//!   instead, and closing that connection unsubscribes.
+//! - `ADD_TICKERS`/`REMOVE_TICKERS` change the tickers of the running stream in place,
+//!   `ADD_DEPTH`/`REMOVE_DEPTH` its order book subscriptions, and `UNSUBSCRIBE` stops it.
 //!
 //! Note: This file only orchestrates; details such as the exact command format, `Quote`
 //! serialization, and ticker parsing live under the `model` and `receiver` modules.
 #![warn(missing_docs)]
-use crate::model::ping_monitor::PingMonitor;
-use crate::model::quote_generator::{QuoteEvent, QuoteGenerator};
-use crate::receiver::QuoteReceiver;
+use crate::admin::{AdminListener, IncomingAdmin};
+use crate::config::{ServerArgs, ServerConfig};
+use crate::metrics_listener::MetricsListener;
+use quote_server::metrics::metrics;
+use quote_server::model::client_stream::StreamOptions;
+use quote_server::model::ping_monitor::PingMonitor;
+use quote_server::model::quote_generator::{QuoteGenerator, SubscriberId};
+use quote_server::model::quote_history::{HISTORY_CAPACITY, QuoteHistory};
+use quote_server::model::stream_pool::StreamPool;
+use quote_server::model::stream_registry::{SERVER_VERSION, StreamRegistry};
+use crate::receiver::{IncomingCommand, QuoteReceiver};
 use crate::udp_listener::UdpPingListener;
-use crossbeam_channel::{Receiver, Sender, select, unbounded};
-use log::{error, info, warn};
+use clap::Parser;
+use crossbeam_channel::{Sender, bounded, select, unbounded};
+use log::{error, info};
 use quote_common::ParserError;
 use quote_common::Result;
-use quote_common::command::Command;
-use quote_common::net::{COMMAND_PORT, DATA_PORT};
-use quote_common::tickers::Ticker;
-use std::collections::{HashMap, HashSet};
+use quote_common::command::{MAX_RETRANSMIT_QUOTES, Request, Response, Transport};
 use std::net::{SocketAddr, UdpSocket};
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Duration;
+
+/// Capacity of the command, admin and ping-timeout channels into the main loop.
+const CHANNEL_CAPACITY: usize = 1024;
 
-pub mod model;
+mod admin;
+mod config;
+mod metrics_listener;
 mod receiver;
 mod udp_listener;
 
-/// Stream task for a single client.
-///
-/// Listens for quote events on `data_rx`, filters them by the client's `tickers`, and
-/// forwards matching quotes to the client's `target_addr` via the provided UDP `socket`.
-/// The task terminates when either:
-/// - a shutdown signal is received on `stop_rx`, or
-/// - a `QuoteEvent::Shutdown` is received from the quote generator, or
-/// - a send/receive error occurs.
-///
-/// Errors are propagated as `ParserError` so the caller can log and recover per client.
-pub fn handle_client_stream(
-    socket: Arc<UdpSocket>,
-    target_addr: SocketAddr,
-    tickers: Vec<Ticker>,
-    data_rx: Receiver<QuoteEvent>,
-    stop_rx: Receiver<()>,
-) -> Result<(), ParserError> {
-    let tickers_set: HashSet<String> = tickers.iter().map(|t| t.to_string()).collect();
-
-    loop {
-        select! {
-            recv(stop_rx) -> _ => break,
-            recv(data_rx) -> msg => match msg {
-                Ok(QuoteEvent::Quote(quote)) => {
-                    if tickers_set.contains(&quote.ticker) {
-                        match quote.to_json_bytes() {
-                            Ok(data) => {
-                                if let Err(e) = socket.send_to(&data, target_addr) {
-                                    error!("Failed to send UDP packet to {}: {}", target_addr, e);
-                                    break;
-                                }
-                            }
-                            Err(e) => {
-                                error!("Failed to serialize quote to JSON: {}", e);
-                                break;
-                            }
-                        }
-                    }
-                },
-                Ok(QuoteEvent::Shutdown) => break,
-                Err(e) => {
-                    error!("Ошибка при получении сообщения: {}", e);
-                    break;
-                },
-            }
-        }
-    }
-    Ok(())
-}
-
 fn main() -> Result<(), ParserError> {
     init_logger();
-    let udp_socket = Arc::new(UdpSocket::bind(format!("0.0.0.0:{}", DATA_PORT))?);
+    let args = ServerArgs::parse();
+    let config = ServerConfig::load(&args)?;
+    info!("Server configuration: {:?}", config);
+
+    let udp_socket = Arc::new(UdpSocket::bind(config.data_addr)?);
     info!("UDP socket created on: {}", udp_socket.local_addr()?);
     let ping_socket = Arc::clone(&udp_socket);
-    let ping_monitor = Arc::new(Mutex::new(PingMonitor::new(5)));
-    let (stop_tx, stop_rx) = unbounded::<SocketAddr>();
+    let ping_monitor = Arc::new(Mutex::new(PingMonitor::new(config.ping_timeout_secs, config.ping_grace_secs)));
+    let (stop_tx, stop_rx) = bounded::<SocketAddr>(CHANNEL_CAPACITY);
+    let timeouts = stop_tx.clone();
+    metrics().register_queue("ping_timeouts", move || timeouts.len());
     let ping_monitor_clone = Arc::clone(&ping_monitor);
     thread::spawn(move || {
         UdpPingListener::start(ping_socket, ping_monitor_clone);
@@ -115,53 +114,159 @@ fn main() -> Result<(), ParserError> {
         start_ping_monitor(ping_monitor_for_checker, stop_tx_clone);
     });
 
-    let (cmd_tx, cmd_rx) = unbounded::<(Command, SocketAddr)>();
-    let tcp_receiver = QuoteReceiver::new(&format!("0.0.0.0:{}", COMMAND_PORT))?;
+    let (cmd_tx, cmd_rx) = bounded::<IncomingCommand>(CHANNEL_CAPACITY);
+    let commands = cmd_tx.clone();
+    metrics().register_queue("commands", move || commands.len());
+    let tcp_receiver = QuoteReceiver::new(config.command_addr)?;
     thread::spawn(move || {
         if let Err(e) = tcp_receiver.receive_loop_with_channel(cmd_tx) {
             error!("Receiver loop failed: {:?}", e);
         };
     });
 
-    let subscription_tx = QuoteGenerator::start();
-    let mut active_streams: HashMap<SocketAddr, (Sender<()>, Sender<QuoteEvent>)> = HashMap::new();
-    loop {
-        select! {
-            recv(cmd_rx) -> msg => if let Ok((cmd, target_udp_addr)) = msg {
-                let (shutdown_tx, shutdown_rx) = unbounded::<()>();
-                let (client_data_tx, client_data_rx) = unbounded::<QuoteEvent>();
+    let (admin_tx, admin_rx) = bounded::<IncomingAdmin>(CHANNEL_CAPACITY);
+    let admin_requests = admin_tx.clone();
+    metrics().register_queue("admin", move || admin_requests.len());
+    let admin_listener = AdminListener::new(config.admin_addr)?;
+    thread::spawn(move || {
+        if let Err(e) = admin_listener.serve(admin_tx) {
+            error!("Admin loop failed: {}", e);
+        }
+    });
 
-                if let Err(e) = subscription_tx.send(client_data_tx.clone()) {
-                    error!("Failed to subscribe client: {}", e);
-                    continue;
-                }
-                active_streams.insert(target_udp_addr, (shutdown_tx, client_data_tx));
+    let metrics_listener = MetricsListener::new(config.metrics_addr)?;
+    thread::spawn(move || {
+        if let Err(e) = metrics_listener.serve() {
+            error!("Metrics loop failed: {}", e);
+        }
+    });
 
-                let socket_clone = Arc::clone(&udp_socket);
-                let tickers = cmd.tickers;
+    let (signal_tx, signal_rx) = bounded::<()>(1);
+    ctrlc::set_handler(move || {
+        let _ = signal_tx.try_send(());
+    })
+    .map_err(|e| ParserError::Config(format!("cannot install signal handler: {}", e)))?;
 
-                thread::spawn(move || {
-                    if let Err(e) = handle_client_stream(
-                        socket_clone,
-                        target_udp_addr,
+    let generator = QuoteGenerator::start(config.generator());
+    let subscriptions = generator.subscriptions();
+    let routing = subscriptions.clone();
+    metrics().register_queue("routing", move || routing.pending());
+    let history = QuoteHistory::start(&subscriptions, &config.tickers, HISTORY_CAPACITY)?;
+    // Each stream is reported at most once, so this channel is bounded by the stream count.
+    let (closed_tx, closed_rx) = unbounded::<SubscriberId>();
+    let pool = StreamPool::start(config.stream_workers, closed_tx);
+    let mut registry = StreamRegistry::new(
+        Arc::clone(&udp_socket),
+        subscriptions,
+        pool,
+        Arc::clone(&ping_monitor),
+        config.tickers.clone(),
+    );
+    loop {
+        select! {
+            recv(cmd_rx) -> msg => if let Ok(incoming) = msg {
+                let IncomingCommand { request, target_addr, session, connection, reply_tx } = incoming;
+                let response = match request {
+                    Request::Hello { .. } => Response::failure(SERVER_VERSION, "HELLO is answered by the connection".to_string()),
+                    Request::Subscribe {
+                        endpoint,
                         tickers,
-                        client_data_rx,
-                        shutdown_rx,
-                    ) {
-                        error!("Client stream error: {:?}", e);
+                        depth,
+                        encoding,
+                        max_batch_delay_ms,
+                        conflate_ms,
+                        max_updates_per_sec,
+                        ping_interval_ms,
+                        ping_timeout_ms,
+                    } => {
+                        let options = StreamOptions {
+                            session,
+                            encoding,
+                            max_batch_delay: Duration::from_millis(max_batch_delay_ms),
+                            queue: config.queue(),
+                            conflation: Request::conflation_interval(conflate_ms, max_updates_per_sec),
+                        };
+                        // A TCP stream ends with its connection; only UDP subscribers must ping.
+                        let over_udp = endpoint.transport == Transport::Udp;
+                        let connection = (!over_udp).then_some(connection);
+                        let mut response = registry.subscribe(target_addr, &tickers, &depth, options, connection);
+                        if over_udp && response.is_success() {
+                            let mut monitor = ping_monitor.lock()?;
+                            let terms = monitor.negotiate(
+                                ping_interval_ms.map(Duration::from_millis),
+                                ping_timeout_ms.map(Duration::from_millis),
+                            );
+                            monitor.register(target_addr, terms);
+                            info!(
+                                "Stream {} must ping every {} ms (timeout {} ms)",
+                                target_addr, terms.interval_ms, terms.timeout_ms
+                            );
+                            response.ping = Some(terms);
+                        }
+                        response
+                    }
+                    Request::Ping { .. } => {
+                        // Only UDP streams are kept alive by pings; a TCP stream lasts as long
+                        // as its connection.
+                        if registry.transport(target_addr) == Some(Transport::Udp) {
+                            ping_monitor.lock()?.update_ping(target_addr);
+                        }
+                        registry.status(target_addr)
+                    }
+                    Request::Unsubscribe { .. } => registry.unsubscribe(target_addr),
+                    Request::AddTickers { tickers, .. } => registry.update_tickers(target_addr, &tickers, true),
+                    Request::RemoveTickers { tickers, .. } => registry.update_tickers(target_addr, &tickers, false),
+                    Request::AddDepth { tickers, .. } => registry.update_depth(target_addr, &tickers, true),
+                    Request::RemoveDepth { tickers, .. } => registry.update_depth(target_addr, &tickers, false),
+                    // Only a client with a stream may ask for its quotes again.
+                    Request::Retransmit { .. } if registry.transport(target_addr).is_none() => {
+                        Response::failure(SERVER_VERSION, format!("no active subscription for {}", target_addr))
+                    }
+                    Request::Retransmit { ticker, from_seq, to_seq, .. } => {
+                        let to_seq = to_seq.min(from_seq.saturating_add(MAX_RETRANSMIT_QUOTES - 1));
+                        match history.range(&ticker.to_string(), from_seq, to_seq) {
+                            Ok(quotes) => {
+                                info!(
+                                    "Retransmitting {} {} quotes (seq {}..={}) to {}",
+                                    quotes.len(), ticker, from_seq, to_seq, target_addr
+                                );
+                                Response::retransmit(SERVER_VERSION, quotes)
+                            }
+                            Err(e) => Response::failure(SERVER_VERSION, e.to_string()),
+                        }
                     }
-                });
-                info!("A stream has been created for the client on a UDP address.: {}", target_udp_addr);
+                };
+                let _ = reply_tx.send(response);
             },
 
-            recv(stop_rx) -> addr => if let Ok(client_addr) = addr {
-                if let Some((shutdown_tx, _)) = active_streams.remove(&client_addr) {
-                    let _ = shutdown_tx.send(());
-                    info!("Stream for {} closed due to ping timeout", client_addr);
-                }
-            }
+            recv(admin_rx) -> msg => if let Ok(IncomingAdmin { request, reply_tx }) = msg {
+                let response = admin::answer(request, &mut registry, &generator, &ping_monitor, &config.tickers);
+                let _ = reply_tx.send(response);
+            },
+
+            recv(stop_rx) -> addr => if let Ok(client_addr) = addr
+                && registry.close(client_addr).is_some()
+            {
+                metrics().ping_timeout();
+                info!("Stream for {} closed due to ping timeout", client_addr);
+            },
+
+            recv(closed_rx) -> id => if let Ok(id) = id
+                && let Some(client_addr) = registry.forget(id)
+            {
+                info!("Stream for {} closed by its worker", client_addr);
+            },
+
+            recv(signal_rx) -> _ => break,
         }
     }
+
+    info!("Shutdown requested; no longer accepting commands");
+    drop(cmd_rx);
+    generator.shutdown();
+    registry.shutdown();
+    info!("All client streams stopped. Server exiting.");
+    Ok(())
 }
 
 fn start_ping_monitor(ping_monitor: Arc<Mutex<PingMonitor>>, stop_tx: Sender<SocketAddr>) {
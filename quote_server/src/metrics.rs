@@ -0,0 +1,221 @@
+//! Process-wide operational metrics in the Prometheus text format.
+//!
+//! Every building block records into the single `Metrics` registry returned by `metrics()`:
+//!
+//! - `StreamRegistry` — connected clients;
+//! - `QuoteGenerator` — quotes generated and subscribers per ticker;
+//! - `ClientStream` — datagrams/frames and bytes sent, and send errors, per transport;
+//! - `SubscriberQueue` — quotes waiting in subscriber queues and quotes dropped by overflow
+//!   policies;
+//! - `PingMonitor` — pings received;
+//! - the server's main loop and command receiver — streams closed by ping timeouts,
+//!   commands by verb and open command connections.
+//!
+//! Channel queue depths are sampled when the metrics are rendered, through probes
+//! registered with `Metrics::register_queue`. Counters only ever grow; gauges reflect the
+//! moment of the scrape. Recording is lock-free except for the per-verb and per-ticker
+//! maps, which are only touched on commands and subscription changes.
+
+use quote_common::command::Transport;
+use quote_common::tickers::Ticker;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex, MutexGuard, PoisonError};
+
+/// Label value of requests that could not be decoded.
+pub const INVALID_VERB: &str = "invalid";
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+/// The process-wide metrics registry.
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+/// Callback that reports how many messages wait in a channel.
+type QueueProbe = Box<dyn Fn() -> usize + Send + Sync>;
+
+/// Send counters of one transport.
+#[derive(Default)]
+struct SendCounters {
+    messages: AtomicU64,
+    bytes: AtomicU64,
+    errors: AtomicU64,
+}
+
+/// Counters and gauges of the server.
+#[derive(Default)]
+pub struct Metrics {
+    connected_clients: AtomicU64,
+    command_connections: AtomicI64,
+    commands: Mutex<BTreeMap<&'static str, u64>>,
+    quotes_generated: AtomicU64,
+    ticker_subscribers: Mutex<BTreeMap<String, usize>>,
+    udp: SendCounters,
+    tcp: SendCounters,
+    queued_quotes: AtomicI64,
+    dropped_quotes: AtomicU64,
+    pings: AtomicU64,
+    ping_timeouts: AtomicU64,
+    queues: Mutex<Vec<(&'static str, QueueProbe)>>,
+}
+
+impl Metrics {
+    /// Set the number of clients with a live stream.
+    pub fn set_connected_clients(&self, count: usize) {
+        self.connected_clients.store(count as u64, Ordering::Relaxed);
+    }
+
+    /// Count a command connection being opened (`true`) or closed (`false`).
+    pub fn command_connection(&self, open: bool) {
+        self.command_connections.fetch_add(if open { 1 } else { -1 }, Ordering::Relaxed);
+    }
+
+    /// Count a received command by its verb (`INVALID_VERB` if it could not be decoded).
+    pub fn command(&self, verb: &'static str) {
+        *lock(&self.commands).entry(verb).or_default() += 1;
+    }
+
+    /// Count generated quotes.
+    pub fn quotes_generated(&self, count: u64) {
+        self.quotes_generated.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Replace the per-ticker subscriber counts; tickers not listed drop to zero.
+    pub fn set_ticker_subscribers<'a>(&self, counts: impl Iterator<Item = (&'a Ticker, usize)>) {
+        let mut subscribers = lock(&self.ticker_subscribers);
+        subscribers.values_mut().for_each(|count| *count = 0);
+        for (ticker, count) in counts {
+            subscribers.insert(ticker.to_string(), count);
+        }
+    }
+
+    /// Count a payload of `bytes` sent to a client over `transport`.
+    pub fn sent(&self, transport: Transport, bytes: usize) {
+        let counters = self.send_counters(transport);
+        counters.messages.fetch_add(1, Ordering::Relaxed);
+        counters.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Count a failed send to a client over `transport`.
+    pub fn send_error(&self, transport: Transport) {
+        self.send_counters(transport).errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Adjust the number of quotes waiting in subscriber queues.
+    pub fn queued_quotes(&self, delta: i64) {
+        self.queued_quotes.fetch_add(delta, Ordering::Relaxed);
+    }
+
+    /// Count a quote discarded by an overflow policy.
+    pub fn dropped_quote(&self) {
+        self.dropped_quotes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a received ping.
+    pub fn ping(&self) {
+        self.pings.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a stream closed because its client stopped pinging.
+    pub fn ping_timeout(&self) {
+        self.ping_timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Report the depth of the channel `name`, sampled by `probe` on every render.
+    pub fn register_queue(&self, name: &'static str, probe: impl Fn() -> usize + Send + Sync + 'static) {
+        lock(&self.queues).push((name, Box::new(probe)));
+    }
+
+    /// Render every metric in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let load = |value: &AtomicU64| value.load(Ordering::Relaxed);
+
+        gauge(&mut out, "connected_clients", "Clients with a live quote stream.");
+        sample(&mut out, "connected_clients", "", load(&self.connected_clients));
+        gauge(&mut out, "command_connections", "Open TCP command connections.");
+        sample(&mut out, "command_connections", "", self.command_connections.load(Ordering::Relaxed));
+
+        counter(&mut out, "commands_total", "Commands received, by verb.");
+        for (verb, count) in lock(&self.commands).iter() {
+            sample(&mut out, "commands_total", &format!("verb=\"{}\"", verb), count);
+        }
+
+        counter(&mut out, "quotes_generated_total", "Quotes produced by the generator.");
+        sample(&mut out, "quotes_generated_total", "", load(&self.quotes_generated));
+        gauge(
+            &mut out,
+            "ticker_subscribers",
+            "Subscribers each ticker is routed to, including the quote history.",
+        );
+        for (ticker, count) in lock(&self.ticker_subscribers).iter() {
+            sample(&mut out, "ticker_subscribers", &format!("ticker=\"{}\"", ticker), count);
+        }
+
+        counter(&mut out, "messages_sent_total", "Datagrams or frames sent to clients, by transport.");
+        self.per_transport(&mut out, "messages_sent_total", |counters| &counters.messages);
+        counter(&mut out, "bytes_sent_total", "Payload bytes sent to clients, by transport.");
+        self.per_transport(&mut out, "bytes_sent_total", |counters| &counters.bytes);
+        counter(&mut out, "send_errors_total", "Failed sends to clients, by transport.");
+        self.per_transport(&mut out, "send_errors_total", |counters| &counters.errors);
+
+        gauge(&mut out, "queued_quotes", "Quotes waiting in subscriber queues, including the quote history's.");
+        sample(&mut out, "queued_quotes", "", self.queued_quotes.load(Ordering::Relaxed));
+        counter(&mut out, "dropped_quotes_total", "Quotes discarded by subscriber overflow policies.");
+        sample(&mut out, "dropped_quotes_total", "", load(&self.dropped_quotes));
+
+        counter(&mut out, "pings_total", "Pings received from UDP clients.");
+        sample(&mut out, "pings_total", "", load(&self.pings));
+        counter(&mut out, "ping_timeouts_total", "UDP clients dropped for not pinging.");
+        sample(&mut out, "ping_timeouts_total", "", load(&self.ping_timeouts));
+
+        gauge(&mut out, "queue_depth", "Messages waiting in internal channels, by channel.");
+        for (name, probe) in lock(&self.queues).iter() {
+            sample(&mut out, "queue_depth", &format!("queue=\"{}\"", name), probe());
+        }
+        out
+    }
+
+    /// Write one sample of `name` per transport, read from its `SendCounters` by `value`.
+    fn per_transport(&self, out: &mut String, name: &str, value: impl Fn(&SendCounters) -> &AtomicU64) {
+        for transport in [Transport::Udp, Transport::Tcp] {
+            let count = value(self.send_counters(transport)).load(Ordering::Relaxed);
+            sample(out, name, &format!("transport=\"{}\"", transport), count);
+        }
+    }
+
+    fn send_counters(&self, transport: Transport) -> &SendCounters {
+        match transport {
+            Transport::Udp => &self.udp,
+            Transport::Tcp => &self.tcp,
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // Metrics stay usable after a panic elsewhere; a partial update is harmless.
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn counter(out: &mut String, name: &str, help: &str) {
+    header(out, name, help, "counter");
+}
+
+fn gauge(out: &mut String, name: &str, help: &str) {
+    header(out, name, help, "gauge");
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP quote_server_{} {}", name, help);
+    let _ = writeln!(out, "# TYPE quote_server_{} {}", name, kind);
+}
+
+fn sample(out: &mut String, name: &str, labels: &str, value: impl std::fmt::Display) {
+    if labels.is_empty() {
+        let _ = writeln!(out, "quote_server_{} {}", name, value);
+    } else {
+        let _ = writeln!(out, "quote_server_{}{{{}}} {}", name, labels, value);
+    }
+}
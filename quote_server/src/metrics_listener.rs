@@ -0,0 +1,107 @@
+//! Minimal HTTP endpoint for Prometheus scrapes.
+//!
+//! `MetricsListener` answers `GET /metrics` with the process-wide `Metrics` rendered in the
+//! Prometheus text format, and anything else with `404`. Every connection is answered
+//! once and closed, so no HTTP library is needed.
+//!
+//! The request head is read with a size limit and a deadline for the whole head, so a peer
+//! that sends slowly or without end cannot hold a thread or its memory for long.
+use log::{debug, error, info};
+use quote_common::ParserError;
+use quote_server::metrics::metrics;
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a scrape may take to send its whole request head.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Longest request head accepted, in bytes.
+const MAX_HEAD_BYTES: u64 = 8 * 1024;
+/// Content type of the Prometheus text exposition format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// HTTP listener serving the metrics.
+pub struct MetricsListener {
+    socket: TcpListener,
+}
+
+impl MetricsListener {
+    /// Bind the metrics listener to `bind_addr`.
+    pub fn new(bind_addr: SocketAddr) -> Result<Self, ParserError> {
+        let socket = TcpListener::bind(bind_addr)?;
+        Ok(Self { socket })
+    }
+
+    /// Blocking loop that answers every connection on its own thread.
+    pub fn serve(self) -> Result<(), ParserError> {
+        info!("Metrics HTTP server is started on {}", self.socket.local_addr()?);
+        for stream in self.socket.incoming() {
+            match stream {
+                Ok(stream) => {
+                    thread::spawn(move || {
+                        if let Err(e) = Self::answer(stream) {
+                            debug!("Failed to answer metrics request: {}", e);
+                        }
+                    });
+                }
+                Err(e) => error!("Metrics connection error: {}", e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Read one request head and write the response.
+    fn answer(stream: TcpStream) -> Result<(), ParserError> {
+        let head = DeadlineReader { stream: stream.try_clone()?, deadline: Instant::now() + REQUEST_TIMEOUT };
+        let mut reader = BufReader::new(head.take(MAX_HEAD_BYTES));
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        // Skip the headers; the request has no body we care about.
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+                break;
+            }
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let (status, body) = match (parts.next(), parts.next()) {
+            _ if reader.get_ref().limit() == 0 => {
+                ("431 Request Header Fields Too Large", "request head is too large\n".to_string())
+            }
+            (Some("GET"), Some("/metrics")) => ("200 OK", metrics().render()),
+            (Some("GET"), Some(_)) => ("404 Not Found", "not found\n".to_string()),
+            _ => ("405 Method Not Allowed", "only GET /metrics is served\n".to_string()),
+        };
+        let mut stream = stream;
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            CONTENT_TYPE,
+            body.len(),
+            body
+        )?;
+        stream.flush()?;
+        Ok(())
+    }
+}
+
+/// Reader of a request head that fails once `deadline` has passed, however slowly the
+/// bytes arrive.
+struct DeadlineReader {
+    stream: TcpStream,
+    deadline: Instant,
+}
+
+impl Read for DeadlineReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::Error::new(ErrorKind::TimedOut, "request head took too long"));
+        }
+        self.stream.set_read_timeout(Some(remaining))?;
+        self.stream.read(buf)
+    }
+}
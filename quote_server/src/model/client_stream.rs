@@ -0,0 +1,411 @@
+//! Per-client quote stream state.
+//!
+//! Every subscription is a `ClientStream` owned by one worker of the `StreamPool`. The
+//! generator routes only the quotes of the client's tickers to its bounded queue, and the
+//! worker hands them to the stream, which sends them through the client's `QuoteSink`:
+//! datagrams to its UDP address, or frames on its TCP command connection. Ticker changes
+//! are applied to the generator's routing, so the stream itself never filters.
+//!
+//! Frames of a TCP stream are written by a `TcpWriter` thread of their own, since a client
+//! that stops reading would otherwise block the worker and every other stream it owns. The
+//! worker only queues the frames; a stream whose writer fell `TCP_WRITE_QUEUE_CAPACITY`
+//! frames behind, or whose write failed, is dropped like any other failed stream.
+//!
+//! When the client negotiated batching, quotes are coalesced into datagrams of at most
+//! `MAX_DATAGRAM_SIZE` bytes. A batch is sent when the next quote would not fit, when no
+//! further quote is immediately ready for the worker and the oldest quote has waited
+//! `max_batch_delay`, or when the stream stops.
+//!
+//! When the client asked for conflation, only the latest quote of each ticker is kept; at
+//! the end of every `conflation` interval the kept quotes are sent together (batched if
+//! allowed). The interval starts with the first quote after the previous release.
+//!
+//! Book messages of depth subscriptions bypass both and are sent as soon as they arrive.
+
+use crate::metrics::metrics;
+use crate::model::subscriber_queue::QueueConfig;
+use crossbeam_channel::{Receiver, Sender, TrySendError, bounded};
+use log::{info, warn};
+use quote_common::ParserError;
+use quote_common::book::BookMessage;
+use quote_common::command::Transport;
+use quote_common::framing::write_frame;
+use quote_common::handshake::{Encoding, Session};
+use quote_common::quote::{MAX_DATAGRAM_SIZE, Quote, QuoteCodec, StreamNotice};
+use std::net::{Shutdown, SocketAddr, TcpStream, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Longest batch delay a client may request.
+pub const MAX_BATCH_DELAY: Duration = Duration::from_secs(1);
+/// Longest conflation interval a client may request.
+pub const MAX_CONFLATION: Duration = Duration::from_secs(60);
+/// Extra bytes reserved per datagram for batch framing (array brackets, counts, commas).
+const BATCH_OVERHEAD: usize = 8;
+/// Frames that may wait for a TCP client's writer before the client counts as stalled.
+const TCP_WRITE_QUEUE_CAPACITY: usize = 256;
+
+/// Per-subscription settings fixed when the stream is created.
+#[derive(Debug, Clone)]
+pub struct StreamOptions {
+    /// Protocol session negotiated on the client's command connection.
+    pub session: Session,
+    /// Encoding of quote datagrams chosen in the subscribe request.
+    pub encoding: Encoding,
+    /// Longest time a quote may wait to be batched (capped at `MAX_BATCH_DELAY`).
+    pub max_batch_delay: Duration,
+    /// Bound and overflow policy of the client's queue.
+    pub queue: QueueConfig,
+    /// Interval at which the latest quote of each ticker is sent, if the client asked for
+    /// conflation (capped at `MAX_CONFLATION`).
+    pub conflation: Option<Duration>,
+}
+
+/// Where a client stream delivers its encoded quotes.
+#[derive(Debug)]
+pub enum QuoteSink {
+    /// One datagram per payload, sent to the client's UDP address.
+    Udp(Arc<UdpSocket>),
+    /// One frame per payload on the client's command connection, written by the
+    /// connection's `TcpWriter`.
+    Tcp(TcpWriter),
+}
+
+impl QuoteSink {
+    /// Send one encoded payload to `target_addr`, counting it in the metrics. A TCP payload
+    /// is only queued here and counted by its writer.
+    fn send(&self, data: &[u8], target_addr: SocketAddr) -> Result<(), ParserError> {
+        match self {
+            QuoteSink::Udp(socket) => match socket.send_to(data, target_addr) {
+                Ok(_) => {
+                    metrics().sent(Transport::Udp, data.len());
+                    Ok(())
+                }
+                Err(e) => {
+                    metrics().send_error(Transport::Udp);
+                    Err(e.into())
+                }
+            },
+            QuoteSink::Tcp(writer) => writer.send(data.to_vec()).inspect_err(|_| metrics().send_error(Transport::Tcp)),
+        }
+    }
+}
+
+/// Work for a `TcpWriter` thread.
+enum TcpWrite {
+    /// Write one frame.
+    Frame(Vec<u8>),
+    /// Shut the connection down after the frames queued before.
+    Shutdown,
+}
+
+/// Thread that writes the frames of one TCP stream to the client's command connection.
+///
+/// The connection is shared with the connection thread so that responses and quotes never
+/// interleave mid-frame. A write that fails, e.g. after blocking for the connection's write
+/// timeout, shuts the connection down and stops the writer; later sends then fail.
+#[derive(Debug)]
+pub struct TcpWriter {
+    /// Queue of the writer thread; `None` once the writer was drained.
+    frames_tx: Option<Sender<TcpWrite>>,
+    /// Handle to shut the connection down without waiting for a blocked write.
+    shutdown_handle: TcpStream,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl TcpWriter {
+    /// Start the writer of a stream on `connection`, the command connection of the client
+    /// at `target_addr`.
+    pub fn start(connection: Arc<Mutex<TcpStream>>, target_addr: SocketAddr) -> Result<Self, ParserError> {
+        let shutdown_handle = connection.lock()?.try_clone()?;
+        let (frames_tx, frames_rx) = bounded(TCP_WRITE_QUEUE_CAPACITY);
+        let thread = thread::Builder::new()
+            .name(format!("tcp-writer-{}", target_addr))
+            .spawn(move || Self::run(&connection, target_addr, frames_rx))?;
+        Ok(Self { frames_tx: Some(frames_tx), shutdown_handle, thread: Some(thread) })
+    }
+
+    /// Queue one frame; fails if the writer is stalled or has stopped.
+    fn send(&self, data: Vec<u8>) -> Result<(), ParserError> {
+        let Some(frames_tx) = &self.frames_tx else {
+            return Err(ParserError::ChannelSend("TCP writer was drained".to_string()));
+        };
+        frames_tx.try_send(TcpWrite::Frame(data)).map_err(|e| match e {
+            TrySendError::Full(_) => ParserError::ChannelSend(format!(
+                "client fell more than {} frames behind on its TCP stream",
+                TCP_WRITE_QUEUE_CAPACITY
+            )),
+            TrySendError::Disconnected(_) => ParserError::ChannelSend("TCP writer has stopped".to_string()),
+        })
+    }
+
+    /// Shut the connection down once the queued frames are written, or right away if the
+    /// writer is stalled.
+    fn shutdown(&self) {
+        let queued = self
+            .frames_tx
+            .as_ref()
+            .is_some_and(|frames_tx| frames_tx.try_send(TcpWrite::Shutdown).is_ok());
+        if !queued {
+            let _ = self.shutdown_handle.shutdown(Shutdown::Both);
+        }
+    }
+
+    /// Wait until the queued frames are written and stop the writer.
+    fn drain(&mut self) {
+        self.frames_tx = None;
+        if let Some(thread) = self.thread.take()
+            && thread.join().is_err()
+        {
+            warn!("TCP writer panicked");
+        }
+    }
+
+    /// Write frames until the stream ends. The connection outlives a stream that ended
+    /// normally, but is shut down on request or once it cannot be written to.
+    fn run(connection: &Mutex<TcpStream>, target_addr: SocketAddr, frames_rx: Receiver<TcpWrite>) {
+        let shut_down = loop {
+            let data = match frames_rx.recv() {
+                Ok(TcpWrite::Frame(data)) => data,
+                Ok(TcpWrite::Shutdown) => break true,
+                Err(_) => break false,
+            };
+            let result = connection
+                .lock()
+                .map_err(ParserError::from)
+                .and_then(|mut stream| write_frame(&mut *stream, &data));
+            match result {
+                Ok(()) => metrics().sent(Transport::Tcp, data.len()),
+                Err(e) => {
+                    metrics().send_error(Transport::Tcp);
+                    warn!("Stream {}: TCP write failed: {}", target_addr, e);
+                    break true;
+                }
+            }
+        };
+        if shut_down && let Ok(stream) = connection.lock() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+/// Quotes waiting to be sent in the next datagram.
+struct QuoteBatch {
+    codec: &'static dyn QuoteCodec,
+    enabled: bool,
+    max_delay: Duration,
+    quotes: Vec<Quote>,
+    /// Sum of the single-quote encodings of `quotes`; an upper bound for the record sizes.
+    size: usize,
+    /// When the oldest pending quote must be sent.
+    deadline: Option<Instant>,
+}
+
+impl QuoteBatch {
+    fn new(options: &StreamOptions) -> Self {
+        Self {
+            codec: options.encoding.codec(options.session.capabilities.top_of_book),
+            enabled: options.session.capabilities.batching,
+            max_delay: options.max_batch_delay.min(MAX_BATCH_DELAY),
+            quotes: Vec::new(),
+            size: 0,
+            deadline: None,
+        }
+    }
+
+    /// Add `quote`, returning the datagrams that became ready to send.
+    fn push(&mut self, quote: Quote) -> Result<Vec<Vec<u8>>, ParserError> {
+        let encoded = self.codec.encode(&quote)?;
+        if !self.enabled {
+            return Ok(vec![encoded]);
+        }
+
+        let mut ready = Vec::new();
+        let quote_size = encoded.len() + 1;
+        if !self.quotes.is_empty() && self.size + quote_size + BATCH_OVERHEAD > MAX_DATAGRAM_SIZE {
+            ready.extend(self.flush()?);
+        }
+        if self.quotes.is_empty() {
+            self.deadline = Some(Instant::now() + self.max_delay);
+        }
+        self.quotes.push(quote);
+        self.size += quote_size;
+        Ok(ready)
+    }
+
+    /// Encode and clear the pending quotes, if any.
+    fn flush(&mut self) -> Result<Option<Vec<u8>>, ParserError> {
+        self.deadline = None;
+        self.size = 0;
+        let datagram = match self.quotes.len() {
+            0 => return Ok(None),
+            1 => self.codec.encode(&self.quotes[0])?,
+            _ => self.codec.encode_batch(&self.quotes)?,
+        };
+        self.quotes.clear();
+        Ok(Some(datagram))
+    }
+}
+
+/// Latest quote of each ticker waiting for the end of the conflation interval.
+struct Conflation {
+    interval: Duration,
+    /// One quote per ticker, in the order the tickers first ticked in this interval.
+    latest: Vec<Quote>,
+    /// When the kept quotes must be sent.
+    deadline: Option<Instant>,
+}
+
+impl Conflation {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval: interval.min(MAX_CONFLATION),
+            latest: Vec::new(),
+            deadline: None,
+        }
+    }
+
+    /// Keep `quote` in place of any earlier quote of its ticker.
+    fn push(&mut self, quote: Quote) {
+        match self.latest.iter_mut().find(|kept| kept.ticker == quote.ticker) {
+            Some(kept) => *kept = quote,
+            None => {
+                if self.latest.is_empty() {
+                    self.deadline = Some(Instant::now() + self.interval);
+                }
+                self.latest.push(quote);
+            }
+        }
+    }
+
+    /// Take the kept quotes and start a new interval.
+    fn take(&mut self) -> Vec<Quote> {
+        self.deadline = None;
+        std::mem::take(&mut self.latest)
+    }
+}
+
+/// Delivery state of a single client's stream.
+///
+/// A `ClientStream` is owned by one `StreamPool` worker, which feeds it the quotes routed
+/// to the client. Quotes are encoded with the codec chosen in `options`, batched if the
+/// session allows it, and sent to the client's `target_addr` via `sink`. With conflation,
+/// quotes are held back and only the latest of each ticker is sent once per interval.
+///
+/// Every method that sends returns the send or encoding error, after which the worker
+/// drops the stream.
+pub struct ClientStream {
+    sink: QuoteSink,
+    target_addr: SocketAddr,
+    batch: QuoteBatch,
+    conflation: Option<Conflation>,
+}
+
+impl ClientStream {
+    /// Create the stream for a client at `target_addr`.
+    pub fn new(sink: QuoteSink, target_addr: SocketAddr, options: &StreamOptions) -> Self {
+        Self {
+            sink,
+            target_addr,
+            batch: QuoteBatch::new(options),
+            conflation: options.conflation.map(Conflation::new),
+        }
+    }
+
+    /// Address the stream delivers to.
+    pub fn target_addr(&self) -> SocketAddr {
+        self.target_addr
+    }
+
+    /// Queue `quote`, sending every datagram that became ready.
+    pub fn push(&mut self, quote: Quote) -> Result<(), ParserError> {
+        if let Some(conflation) = &mut self.conflation {
+            conflation.push(quote);
+            return Ok(());
+        }
+        self.send_batched(quote)
+    }
+
+    /// When pending quotes must be sent, if anything is pending.
+    pub fn deadline(&self) -> Option<Instant> {
+        let conflation = self.conflation.as_ref().and_then(|conflation| conflation.deadline);
+        match (self.batch.deadline, conflation) {
+            (Some(batch), Some(conflation)) => Some(batch.min(conflation)),
+            (batch, conflation) => batch.or(conflation),
+        }
+    }
+
+    /// Send the pending quotes whose deadline has passed.
+    pub fn flush_if_due(&mut self, now: Instant) -> Result<(), ParserError> {
+        let conflation_due = self
+            .conflation
+            .as_ref()
+            .and_then(|conflation| conflation.deadline)
+            .is_some_and(|deadline| now >= deadline);
+        if conflation_due || self.batch.deadline.is_some_and(|deadline| now >= deadline) {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Send every pending quote, including those held back for conflation.
+    pub fn flush(&mut self) -> Result<(), ParserError> {
+        if let Some(conflation) = &mut self.conflation {
+            for quote in conflation.take() {
+                self.send_batched(quote)?;
+            }
+        }
+        if let Some(data) = self.batch.flush()? {
+            self.sink.send(&data, self.target_addr)?;
+        }
+        Ok(())
+    }
+
+    /// Send the pending quotes followed by a `StreamNotice::GoingAway` with `reason`, and
+    /// wait until they are written.
+    pub fn going_away(&mut self, reason: &str) -> Result<(), ParserError> {
+        self.flush()?;
+        self.send_notice(reason)?;
+        self.drain();
+        info!("Stream {}: sent going-away notice", self.target_addr);
+        Ok(())
+    }
+
+    /// Wait until everything sent so far is written. Only a TCP stream writes later; its
+    /// writer is stopped, so this is for streams that end with the server.
+    pub fn drain(&mut self) {
+        if let QuoteSink::Tcp(writer) = &mut self.sink {
+            writer.drain();
+        }
+    }
+
+    /// End the stream without sending pending quotes: try to tell the client why and, for a
+    /// TCP stream, shut its connection down.
+    pub fn disconnect(&mut self, reason: &str) {
+        let _ = self.send_notice(reason);
+        if let QuoteSink::Tcp(writer) = &self.sink {
+            writer.shutdown();
+        }
+    }
+
+    /// Send a book message of a depth subscription. Book messages are never batched or
+    /// conflated, since dropping an update would leave the client's book wrong until the
+    /// next snapshot.
+    pub fn push_book(&mut self, message: &BookMessage) -> Result<(), ParserError> {
+        self.sink.send(&message.to_bytes()?, self.target_addr)
+    }
+
+    /// Add `quote` to the batch, sending every datagram that became ready.
+    fn send_batched(&mut self, quote: Quote) -> Result<(), ParserError> {
+        for data in self.batch.push(quote)? {
+            self.sink.send(&data, self.target_addr)?;
+        }
+        Ok(())
+    }
+
+    fn send_notice(&self, reason: &str) -> Result<(), ParserError> {
+        let notice = StreamNotice::GoingAway { reason: reason.to_string() };
+        self.sink.send(&notice.to_bytes()?, self.target_addr)
+    }
+}
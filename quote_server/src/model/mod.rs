@@ -5,7 +5,21 @@
 //! - `quote` — market `Quote` type and (de)serialization helpers.
 //! - `tickers` — supported ticker symbols used across the system.
 //! - `ping_monitor` — in-memory keep-alive tracker for client timeouts.
-//! - `quote_generator` — background data generator and `QuoteEvent` broadcasting.
+//! - `quote_generator` — background data generator and per-ticker `QuoteEvent` routing.
+//! - `price_model` — stochastic price processes behind the `PriceModel` trait.
+//! - `order_book` — simulated per-ticker Level 2 books and their `BookConfig`.
+//! - `client_stream` — per-client `ClientStream` state and its `QuoteSink`.
+//! - `subscriber_queue` — bounded per-subscriber queues and the `OverflowPolicy`.
+//! - `stream_pool` — fixed pool of worker threads that own and drive the client streams.
+//! - `stream_registry` — main-loop registry of active client streams.
+//! - `quote_history` — bounded per-ticker ring buffer used to answer retransmit requests.
 
 pub mod ping_monitor;
 pub mod quote_generator;
+pub mod price_model;
+pub mod order_book;
+pub mod client_stream;
+pub mod subscriber_queue;
+pub mod stream_pool;
+pub mod stream_registry;
+pub mod quote_history;
@@ -0,0 +1,227 @@
+//! Simulated Level 2 order books.
+//!
+//! The generator keeps one `SimulatedBook` per ticker and moves it with every quote. The
+//! best level of each side is the quote's bid or ask with its size. The other levels follow
+//! at a fixed spacing of about one basis point of the price, and never less than a cent.
+//! Deeper levels tend to hold more shares. Most levels keep their size from one tick to
+//! the next, so an update lists only the levels that moved or were resized.
+//!
+//! Books draw from the ticker's random source right after its quote, so they are as
+//! reproducible as the prices.
+
+use quote_common::book::{BookLevel, BookMessage, LevelChange, MAX_BOOK_CHANGES, Side};
+use quote_common::quote::{Quote, TICKS_PER_UNIT, from_ticks, to_ticks};
+use quote_common::tickers::Ticker;
+use rand::{Rng, RngCore};
+use std::time::Duration;
+
+/// Shape and publication of the simulated books.
+#[derive(Debug, Clone, Copy)]
+pub struct BookConfig {
+    /// Price levels per side, at most `MAX_BOOK_LEVELS`.
+    pub levels: usize,
+    /// Interval between full snapshots of every book that has depth subscribers.
+    pub snapshot_interval: Duration,
+}
+
+/// A price level: price in ticks (`1 / TICKS_PER_UNIT`) and size.
+type Level = (u64, u32);
+
+/// Order book of one ticker.
+pub struct SimulatedBook {
+    ticker: String,
+    levels: usize,
+    /// Bid levels, best (highest) first.
+    bids: Vec<Level>,
+    /// Ask levels, best (lowest) first.
+    asks: Vec<Level>,
+    /// Sequence number of the last update.
+    seq: u64,
+    /// Timestamp of the last update.
+    timestamp: u64,
+}
+
+impl SimulatedBook {
+    /// Empty book of `ticker` with `levels` price levels per side.
+    pub fn new(ticker: &Ticker, levels: usize) -> Self {
+        Self {
+            ticker: ticker.to_string(),
+            levels,
+            bids: Vec::new(),
+            asks: Vec::new(),
+            seq: 0,
+            timestamp: 0,
+        }
+    }
+
+    /// Move the book to the top of book of `quote` and return the updates describing the
+    /// change, each small enough for one datagram.
+    pub fn advance(&mut self, quote: &Quote, rng: &mut dyn RngCore) -> Vec<BookMessage> {
+        let mid = (quote.bid + quote.ask) / 2.0;
+        let spacing = ((mid * TICKS_PER_UNIT / 10_000.0).round() as u64).max(1);
+        let best_bid = to_ticks(quote.bid);
+        let best_ask = to_ticks(quote.ask);
+
+        let bid_price = |k| best_bid.checked_sub(k * spacing).filter(|&price| price > 0);
+        let bids = self.ladder(&self.bids, bid_price, quote.bid_size, rng);
+        let asks = self.ladder(&self.asks, |k| Some(best_ask + k * spacing), quote.ask_size, rng);
+        let mut changes = diff(Side::Bid, &self.bids, &bids);
+        changes.extend(diff(Side::Ask, &self.asks, &asks));
+        self.bids = bids;
+        self.asks = asks;
+        self.timestamp = quote.timestamp;
+
+        changes
+            .chunks(MAX_BOOK_CHANGES)
+            .map(|chunk| {
+                self.seq += 1;
+                BookMessage::BookUpdate {
+                    ticker: self.ticker.clone(),
+                    seq: self.seq,
+                    timestamp: self.timestamp,
+                    changes: chunk.to_vec(),
+                }
+            })
+            .collect()
+    }
+
+    /// The full book as of the last update.
+    pub fn snapshot(&self) -> BookMessage {
+        let levels = |side: &[Level]| {
+            side.iter()
+                .map(|&(price, size)| BookLevel { price: from_ticks(price), size })
+                .collect()
+        };
+        BookMessage::BookSnapshot {
+            ticker: self.ticker.clone(),
+            seq: self.seq,
+            timestamp: self.timestamp,
+            bids: levels(&self.bids),
+            asks: levels(&self.asks),
+        }
+    }
+
+    /// New levels of one side. Level `k` is priced by `price(k)`; the best level gets
+    /// `best_size`, the others mostly keep their `previous` size.
+    fn ladder(
+        &self,
+        previous: &[Level],
+        price: impl Fn(u64) -> Option<u64>,
+        best_size: u32,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Level> {
+        (0..self.levels as u64)
+            .map_while(|k| {
+                let price = price(k)?;
+                let kept = previous.iter().find(|level| level.0 == price).map(|level| level.1);
+                let size = match kept {
+                    _ if k == 0 => best_size,
+                    Some(size) if rng.random_bool(0.8) => size,
+                    _ => 100 * rng.random_range(1..=5 + 3 * k as u32),
+                };
+                Some((price, size))
+            })
+            .collect()
+    }
+}
+
+/// Changes that turn the `old` levels of `side` into the `new` ones.
+fn diff(side: Side, old: &[Level], new: &[Level]) -> Vec<LevelChange> {
+    let resized = new
+        .iter()
+        .filter(|level| !old.contains(level))
+        .map(|&(price, size)| LevelChange { side, price: from_ticks(price), size });
+    let removed = old
+        .iter()
+        .filter(|(price, _)| !new.iter().any(|level| level.0 == *price))
+        .map(|&(price, _)| LevelChange { side, price: from_ticks(price), size: 0 });
+    resized.chain(removed).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quote_common::book::MAX_BOOK_LEVELS;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    fn quote(bid: f64, ask: f64) -> Quote {
+        Quote {
+            ticker: "AAPL".to_string(),
+            price: bid,
+            volume: 100,
+            timestamp: 1,
+            seq: 1,
+            bid,
+            ask,
+            bid_size: 300,
+            ask_size: 400,
+        }
+    }
+
+    fn changes(message: &BookMessage) -> &[LevelChange] {
+        match message {
+            BookMessage::BookUpdate { changes, .. } => changes,
+            BookMessage::BookSnapshot { .. } => panic!("expected an update"),
+        }
+    }
+
+    #[test]
+    fn the_first_update_lists_every_level() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut book = SimulatedBook::new(&Ticker::AAPL, 5);
+        let updates = book.advance(&quote(190.00, 190.02), &mut rng);
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].seq(), 1);
+        assert_eq!(changes(&updates[0]).len(), 10);
+
+        let BookMessage::BookSnapshot { seq, bids, asks, .. } = book.snapshot() else {
+            panic!("expected a snapshot");
+        };
+        assert_eq!(seq, 1);
+        assert_eq!(bids[0], BookLevel { price: 190.00, size: 300 });
+        assert_eq!(asks[0], BookLevel { price: 190.02, size: 400 });
+        // About one basis point apart, best first.
+        assert_eq!(bids[1].price, 189.98);
+        assert_eq!(asks[4].price, 190.10);
+    }
+
+    #[test]
+    fn updates_number_on_and_remove_levels_left_behind() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut book = SimulatedBook::new(&Ticker::AAPL, 3);
+        book.advance(&quote(190.00, 190.02), &mut rng);
+        let updates = book.advance(&quote(190.02, 190.04), &mut rng);
+        assert_eq!(updates.iter().map(BookMessage::seq).collect::<Vec<_>>(), [2]);
+
+        let changes = changes(&updates[0]);
+        let removed: Vec<(Side, f64)> = changes
+            .iter()
+            .filter(|change| change.size == 0)
+            .map(|change| (change.side, change.price))
+            .collect();
+        assert_eq!(removed, [(Side::Bid, 189.96), (Side::Ask, 190.02)]);
+        assert_eq!(book.snapshot().seq(), 2);
+    }
+
+    #[test]
+    fn large_changes_are_split_into_consecutive_updates() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut book = SimulatedBook::new(&Ticker::AAPL, MAX_BOOK_LEVELS);
+        let updates = book.advance(&quote(190.00, 190.02), &mut rng);
+        assert!(updates.len() > 1);
+        assert!(updates.iter().all(|update| changes(update).len() <= MAX_BOOK_CHANGES));
+        let seqs: Vec<u64> = updates.iter().map(BookMessage::seq).collect();
+        assert_eq!(seqs, (1..=updates.len() as u64).collect::<Vec<_>>());
+        assert_eq!(book.snapshot().seq(), updates.len() as u64);
+    }
+
+    #[test]
+    fn an_unchanged_top_of_book_may_send_nothing() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let mut book = SimulatedBook::new(&Ticker::AAPL, 1);
+        book.advance(&quote(190.00, 190.02), &mut rng);
+        assert!(book.advance(&quote(190.00, 190.02), &mut rng).is_empty());
+        assert_eq!(book.snapshot().seq(), 1);
+    }
+}
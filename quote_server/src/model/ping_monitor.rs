@@ -1,76 +1,126 @@
 //! Ping/keep-alive state tracker for UDP clients.
 //!
 //! This module provides a lightweight, in-memory monitor that tracks the last time a
-//! client (identified by `SocketAddr`) sent a keep-alive/ping. It exposes three core
+//! client (identified by `SocketAddr`) sent a keep-alive/ping. It exposes these core
 //! operations:
 //!
-//! - `PingMonitor::update_ping(addr)` — record a fresh ping for a client and mark it active.
+//! - `PingMonitor::negotiate(interval, timeout)` — agree on the keep-alive terms a client
+//!   asked for in its subscribe command, within the server's limits.
+//! - `PingMonitor::register(addr, terms)` — start watching a new subscriber. The first ping
+//!   may take up to the grace period longer than the timeout, but a client that never
+//!   pings is timed out like any other.
+//! - `PingMonitor::update_ping(addr)` — record a fresh ping for a registered client. Pings
+//!   from any other address are ignored.
 //! - `PingMonitor::check_timeouts()` — scan all clients and return the addresses that have
-//!   exceeded the configured timeout; those clients are marked inactive internally.
-//! - `PingMonitor::is_client_active(addr)` — read-only check whether a client is currently
-//!   considered active.
+//!   exceeded their timeout; those clients are removed, so each is reported once.
+//! - `PingMonitor::is_client_active(addr)` — read-only check whether a client is registered
+//!   and has not timed out.
+//! - `PingMonitor::last_ping(addr)` — when the client last pinged, for the admin interface.
+//! - `PingMonitor::remove(addr)` — stop watching a client whose stream was ended.
 //!
 //! Design notes:
 //! - Time is measured using `std::time::Instant`, which is monotonic and immune to system
 //!   clock changes.
 //! - The monitor is not synchronized; if it is shared across threads, wrap it with a
 //!   synchronization primitive (e.g., `Mutex` or `RwLock`).
-//! - `check_timeouts` is idempotent between pings: once a client times out, it stays
-//!   inactive until the next `update_ping` marks it active again.
+//! - A client that timed out is forgotten; its later pings are ignored until it subscribes
+//!   and is registered again.
 
+use crate::metrics::metrics;
+use quote_common::command::PingTerms;
 use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::time::{Duration, Instant};
 
+/// Shortest ping timeout a client may negotiate.
+pub const MIN_PING_TIMEOUT: Duration = Duration::from_secs(1);
+/// Longest ping timeout a client may negotiate.
+pub const MAX_PING_TIMEOUT: Duration = Duration::from_secs(300);
+/// Shortest ping interval a client may negotiate.
+pub const MIN_PING_INTERVAL: Duration = Duration::from_millis(100);
+
 /// Internal bookkeeping for a client connection.
 ///
-/// This is intentionally minimal: last observed ping time and a cached `is_active` flag
-/// to avoid re-emitting the same timeout multiple times between pings.
+/// This is intentionally minimal: when the client times out unless it pings, its
+/// negotiated timeout, and its last ping (if any).
 struct ClientConnection {
-    last_ping: Instant,
-    is_active: bool,
+    expires_at: Instant,
+    timeout: Duration,
+    last_ping: Option<Instant>,
 }
 
 /// Tracks client keep-alive pings and determines inactivity based on a timeout.
 pub struct PingMonitor {
-    /// All known clients with their last ping time and active flag.
+    /// Registered clients with their deadline and last ping time.
     clients: HashMap<SocketAddr, ClientConnection>,
-    /// Threshold after which a client is considered timed out.
+    /// Threshold after which a client is considered timed out, unless it negotiated another.
     timeout: Duration,
+    /// Extra time a newly registered client has to send its first ping.
+    grace: Duration,
 }
 
 impl PingMonitor {
     /// Create a new instance of PingMonitor
-    pub fn new(timeout_secs: u64) -> Self {
+    pub fn new(timeout_secs: u64, grace_secs: u64) -> Self {
         Self {
             clients: HashMap::new(),
             timeout: Duration::from_secs(timeout_secs),
+            grace: Duration::from_secs(grace_secs),
+        }
+    }
+
+    /// Agree on keep-alive terms from the client's requested interval and timeout.
+    ///
+    /// The timeout defaults to the server's, or to three intervals if that is longer, and
+    /// is kept within `MIN_PING_TIMEOUT..=MAX_PING_TIMEOUT`. The interval defaults to a
+    /// third of the timeout and is at most half of it, so one lost ping is tolerated.
+    pub fn negotiate(&self, interval: Option<Duration>, timeout: Option<Duration>) -> PingTerms {
+        let timeout = timeout
+            .unwrap_or_else(|| self.timeout.max(interval.unwrap_or_default() * 3))
+            .clamp(MIN_PING_TIMEOUT, MAX_PING_TIMEOUT);
+        let interval = interval
+            .unwrap_or(timeout / 3)
+            .clamp(MIN_PING_INTERVAL, timeout / 2);
+        PingTerms {
+            interval_ms: interval.as_millis() as u64,
+            timeout_ms: timeout.as_millis() as u64,
         }
     }
 
-    /// Update existing PingMonitor
-    pub fn update_ping(&mut self, addr: SocketAddr) {
+    /// Start watching a newly subscribed client under `terms`, allowing the grace period
+    /// for its first ping.
+    pub fn register(&mut self, addr: SocketAddr, terms: PingTerms) {
+        let timeout = Duration::from_millis(terms.timeout_ms);
+        self.clients.insert(
+            addr,
+            ClientConnection {
+                expires_at: Instant::now() + timeout + self.grace,
+                timeout,
+                last_ping: None,
+            },
+        );
+    }
+
+    /// Record a ping from `addr`. Returns `false`, leaving the monitor unchanged, if the
+    /// client is not registered.
+    pub fn update_ping(&mut self, addr: SocketAddr) -> bool {
+        metrics().ping();
+        let Some(conn) = self.clients.get_mut(&addr) else {
+            return false;
+        };
         let now = Instant::now();
-        self.clients
-            .entry(addr)
-            .and_modify(|conn| {
-                conn.last_ping = now;
-                conn.is_active = true;
-            })
-            .or_insert(ClientConnection {
-                last_ping: now,
-                is_active: true,
-            });
+        conn.expires_at = now + conn.timeout;
+        conn.last_ping = Some(now);
+        true
     }
 
-    /// Check if timeout less max interval between pings/data
+    /// Remove and return the clients whose deadline has passed.
     pub fn check_timeouts(&mut self) -> Vec<SocketAddr> {
         let now = Instant::now();
-        let timeout = self.timeout;
         let mut timed_out = Vec::new();
 
         self.clients.retain(|addr, conn| {
-            if now.duration_since(conn.last_ping) > timeout {
+            if now > conn.expires_at {
                 timed_out.push(*addr);
                 false
             } else {
@@ -80,11 +130,18 @@ impl PingMonitor {
         timed_out
     }
 
-    /// Check is client connection active
+    /// Check whether the client is registered and has not timed out.
     pub fn is_client_active(&self, addr: &SocketAddr) -> bool {
-        self.clients
-            .get(addr)
-            .map(|conn| conn.is_active)
-            .unwrap_or(false)
+        self.clients.contains_key(addr)
+    }
+
+    /// When the client last pinged, if it has pinged since it was registered.
+    pub fn last_ping(&self, addr: &SocketAddr) -> Option<Instant> {
+        self.clients.get(addr).and_then(|conn| conn.last_ping)
+    }
+
+    /// Stop watching the client after its stream ended.
+    pub fn remove(&mut self, addr: &SocketAddr) {
+        self.clients.remove(addr);
     }
 }
@@ -0,0 +1,297 @@
+//! Stochastic price processes driving the quote generator.
+//!
+//! Every generated ticker owns one `PriceModel` that turns the last price into the next
+//! one. The model and its parameters come from the server's `PriceModelConfig`:
+//!
+//! - `UniformWalk` — the historical uniform ±1% step per tick, with no drift and no scale;
+//! - `Gbm` — geometric Brownian motion, log-normal returns with constant drift and
+//!   volatility;
+//! - `OrnsteinUhlenbeck` — the log price reverts towards a long-run mean at a configured
+//!   speed, for range-bound instruments;
+//! - `MertonJump` — geometric Brownian motion plus Poisson-arriving log-normal jumps, which
+//!   gives the fat tails the other models lack.
+//!
+//! Drift, volatility and jump intensity are annualized. Each tick advances the simulated
+//! clock by the tick interval multiplied by `time_scale`, measured against a trading year of
+//! 252 days of 6.5 hours; the default scale replays one trading minute per wall-clock
+//! second so that prices move visibly at the default tick rate. Per-ticker overrides of the
+//! drift, volatility and mean-reversion level live under `tickers`.
+//!
+//! The models draw from the generator's random source, so the whole price path depends on
+//! it alone. Prices never fall below `MIN_PRICE`.
+
+use clap::ValueEnum;
+use log::info;
+use quote_common::ParserError;
+use quote_common::tickers::Ticker;
+use rand::{Rng, RngCore};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::f64::consts::TAU;
+use std::time::Duration;
+use strum_macros::Display;
+
+/// Lowest price any model produces.
+pub const MIN_PRICE: f64 = 0.01;
+/// Seconds in a trading year: 252 sessions of 6.5 hours.
+const SECONDS_PER_TRADING_YEAR: f64 = 252.0 * 6.5 * 3600.0;
+
+/// Process that produces the next price of one ticker.
+pub trait PriceModel: Send {
+    /// Price one tick after `price`, drawing randomness from `rng`.
+    fn next_price(&mut self, price: f64, rng: &mut dyn RngCore) -> f64;
+}
+
+/// Which price process the generator runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, ValueEnum, Display)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum PriceModelKind {
+    /// Uniform ±1% step per tick.
+    #[default]
+    Uniform,
+    /// Geometric Brownian motion.
+    Gbm,
+    /// Mean-reverting log price (Ornstein-Uhlenbeck).
+    #[serde(alias = "ou")]
+    #[value(alias = "ou")]
+    OrnsteinUhlenbeck,
+    /// Geometric Brownian motion with Poisson jumps (Merton jump-diffusion).
+    Merton,
+}
+
+/// Per-ticker overrides of the model parameters.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TickerModelParams {
+    /// Annualized drift of the ticker.
+    pub drift: Option<f64>,
+    /// Annualized volatility of the ticker.
+    pub volatility: Option<f64>,
+    /// Long-run price the Ornstein-Uhlenbeck model reverts to; defaults to the starting
+    /// price.
+    pub mean: Option<f64>,
+}
+
+/// Price model settings of the generator.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PriceModelConfig {
+    /// Price process used for every ticker.
+    pub kind: PriceModelKind,
+    /// Annualized drift, e.g. `0.05` for 5% a year.
+    pub drift: f64,
+    /// Annualized volatility, e.g. `0.2` for 20% a year.
+    pub volatility: f64,
+    /// Speed of mean reversion of the Ornstein-Uhlenbeck model, per year.
+    pub mean_reversion: f64,
+    /// Expected number of jumps per year in the Merton model.
+    pub jump_intensity: f64,
+    /// Mean of the log jump size in the Merton model.
+    pub jump_mean: f64,
+    /// Standard deviation of the log jump size in the Merton model.
+    pub jump_volatility: f64,
+    /// Simulated market seconds per wall-clock second.
+    pub time_scale: f64,
+    /// Per-ticker overrides.
+    pub tickers: HashMap<Ticker, TickerModelParams>,
+}
+
+impl Default for PriceModelConfig {
+    fn default() -> Self {
+        Self {
+            kind: PriceModelKind::default(),
+            drift: 0.0,
+            volatility: 0.2,
+            mean_reversion: 50.0,
+            jump_intensity: 252.0,
+            jump_mean: -0.01,
+            jump_volatility: 0.03,
+            time_scale: 60.0,
+            tickers: HashMap::new(),
+        }
+    }
+}
+
+impl PriceModelConfig {
+    /// Reject parameters the models cannot run with.
+    pub fn validate(&self) -> Result<(), ParserError> {
+        let invalid = |message: &str| Err(ParserError::Config(format!("price_model: {}", message)));
+        let finite = [self.drift, self.volatility, self.mean_reversion, self.jump_intensity];
+        if finite.into_iter().chain([self.jump_mean, self.jump_volatility]).any(|v| !v.is_finite()) {
+            return invalid("parameters must be finite numbers");
+        }
+        if self.volatility < 0.0 || self.jump_volatility < 0.0 {
+            return invalid("volatilities must not be negative");
+        }
+        if self.mean_reversion < 0.0 || self.jump_intensity < 0.0 {
+            return invalid("mean_reversion and jump_intensity must not be negative");
+        }
+        if !self.time_scale.is_finite() || self.time_scale <= 0.0 {
+            return invalid("time_scale must be positive");
+        }
+        for (ticker, params) in &self.tickers {
+            if params.drift.is_some_and(|drift| !drift.is_finite())
+                || params.volatility.is_some_and(|vol| !vol.is_finite() || vol < 0.0)
+            {
+                return invalid(&format!("{}: drift and volatility must be finite, volatility not negative", ticker));
+            }
+            if params.mean.is_some_and(|mean| !mean.is_finite() || mean <= 0.0) {
+                return invalid(&format!("{}: mean must be positive", ticker));
+            }
+        }
+        Ok(())
+    }
+
+    /// Log the chosen model and its parameters.
+    pub fn log(&self) {
+        match self.kind {
+            PriceModelKind::Uniform => info!("Price model: uniform ±1% walk"),
+            kind => info!(
+                "Price model: {} (drift {}, volatility {}, time scale {}x, {} ticker overrides)",
+                kind,
+                self.drift,
+                self.volatility,
+                self.time_scale,
+                self.tickers.len()
+            ),
+        }
+    }
+
+    /// Model for `ticker`, starting at `start_price` and advanced every `tick_interval`.
+    pub fn build(&self, ticker: &Ticker, start_price: f64, tick_interval: Duration) -> Box<dyn PriceModel> {
+        let params = self.tickers.get(ticker).copied().unwrap_or_default();
+        let drift = params.drift.unwrap_or(self.drift);
+        let volatility = params.volatility.unwrap_or(self.volatility);
+        let dt = tick_interval.as_secs_f64() * self.time_scale / SECONDS_PER_TRADING_YEAR;
+        match self.kind {
+            PriceModelKind::Uniform => Box::new(UniformWalk),
+            PriceModelKind::Gbm => Box::new(Gbm { drift, volatility, dt }),
+            PriceModelKind::OrnsteinUhlenbeck => Box::new(OrnsteinUhlenbeck {
+                mean: params.mean.unwrap_or(start_price),
+                drift,
+                volatility,
+                speed: self.mean_reversion,
+                dt,
+            }),
+            PriceModelKind::Merton => Box::new(MertonJump {
+                diffusion: Gbm { drift, volatility, dt },
+                intensity: self.jump_intensity,
+                jump_mean: self.jump_mean,
+                jump_volatility: self.jump_volatility,
+            }),
+        }
+    }
+}
+
+/// Uniform ±1% step per tick.
+pub struct UniformWalk;
+
+impl PriceModel for UniformWalk {
+    fn next_price(&mut self, price: f64, rng: &mut dyn RngCore) -> f64 {
+        let change: f64 = rng.random_range(-0.01..0.01);
+        (price * (1.0 + change)).max(MIN_PRICE)
+    }
+}
+
+/// Geometric Brownian motion: `dS = μS dt + σS dW`, sampled exactly in log space.
+pub struct Gbm {
+    /// Annualized drift `μ`.
+    pub drift: f64,
+    /// Annualized volatility `σ`.
+    pub volatility: f64,
+    /// Length of one tick, in years.
+    pub dt: f64,
+}
+
+impl Gbm {
+    /// Log return over one tick, with the drift reduced by `compensation`.
+    fn log_return(&self, compensation: f64, rng: &mut dyn RngCore) -> f64 {
+        let Self { drift, volatility, dt } = *self;
+        (drift - compensation - volatility * volatility / 2.0) * dt + volatility * dt.sqrt() * standard_normal(rng)
+    }
+}
+
+impl PriceModel for Gbm {
+    fn next_price(&mut self, price: f64, rng: &mut dyn RngCore) -> f64 {
+        (price * self.log_return(0.0, rng).exp()).max(MIN_PRICE)
+    }
+}
+
+/// Ornstein-Uhlenbeck process on the log price, reverting towards `ln(mean)`.
+///
+/// The long-run mean itself grows with the drift, so a positive drift gives a rising
+/// channel rather than a flat one.
+pub struct OrnsteinUhlenbeck {
+    /// Current long-run price.
+    pub mean: f64,
+    /// Annualized growth of the long-run price.
+    pub drift: f64,
+    /// Annualized volatility of the log price.
+    pub volatility: f64,
+    /// Speed of mean reversion `θ`, per year.
+    pub speed: f64,
+    /// Length of one tick, in years.
+    pub dt: f64,
+}
+
+impl PriceModel for OrnsteinUhlenbeck {
+    fn next_price(&mut self, price: f64, rng: &mut dyn RngCore) -> f64 {
+        self.mean *= (self.drift * self.dt).exp();
+        let level = self.mean.ln();
+        // Exact transition of the process over `dt`; with no reversion it is a plain walk.
+        let (decay, variance) = if self.speed > 0.0 {
+            let decay = (-self.speed * self.dt).exp();
+            (decay, (1.0 - decay * decay) / (2.0 * self.speed))
+        } else {
+            (1.0, self.dt)
+        };
+        let log_price = level + (price.ln() - level) * decay + self.volatility * variance.sqrt() * standard_normal(rng);
+        log_price.exp().max(MIN_PRICE)
+    }
+}
+
+/// Merton jump-diffusion: geometric Brownian motion plus compound Poisson jumps with
+/// normally distributed log sizes.
+pub struct MertonJump {
+    /// Diffusion part of the process.
+    pub diffusion: Gbm,
+    /// Expected number of jumps per year `λ`.
+    pub intensity: f64,
+    /// Mean of the log jump size.
+    pub jump_mean: f64,
+    /// Standard deviation of the log jump size.
+    pub jump_volatility: f64,
+}
+
+impl PriceModel for MertonJump {
+    fn next_price(&mut self, price: f64, rng: &mut dyn RngCore) -> f64 {
+        // Compensate the drift so that jumps do not change the expected return.
+        let mean_jump = (self.jump_mean + self.jump_volatility * self.jump_volatility / 2.0).exp() - 1.0;
+        let mut log_return = self.diffusion.log_return(self.intensity * mean_jump, rng);
+        for _ in 0..poisson(self.intensity * self.diffusion.dt, rng) {
+            log_return += self.jump_mean + self.jump_volatility * standard_normal(rng);
+        }
+        (price * log_return.exp()).max(MIN_PRICE)
+    }
+}
+
+/// Standard normal draw (Box-Muller).
+fn standard_normal(rng: &mut dyn RngCore) -> f64 {
+    // `1 - u` lies in (0, 1], so the logarithm is finite.
+    let u1 = 1.0 - rng.random::<f64>();
+    let u2 = rng.random::<f64>();
+    (-2.0 * u1.ln()).sqrt() * (TAU * u2).cos()
+}
+
+/// Poisson draw with mean `lambda` (Knuth); meant for the small means of a single tick.
+fn poisson(lambda: f64, rng: &mut dyn RngCore) -> u32 {
+    let limit = (-lambda).exp();
+    let mut count = 0;
+    let mut product = rng.random::<f64>();
+    while product > limit {
+        count += 1;
+        product *= rng.random::<f64>();
+    }
+    count
+}
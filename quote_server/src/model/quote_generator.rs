@@ -1,56 +1,506 @@
-//! Quote stream generator and event broadcasting.
+//! Quote stream generator and per-ticker event routing.
 //!
-//! The `QuoteGenerator` runs a background thread that synthesizes `Quote` values for a
-//! fixed set of `Ticker`s and broadcasts them to all subscribed clients using
-//! `crossbeam_channel`. New client tasks register by sending a `Sender<QuoteEvent>` to the
-//! subscription channel returned by `QuoteGenerator::start`.
+//! The `QuoteGenerator` runs a background thread that synthesizes `Quote` values for the
+//! `Ticker`s listed in its `GeneratorConfig` and delivers each quote only to the subscribers
+//! of its ticker, through each subscriber's bounded `SubscriberQueue`. Subscribers register,
+//! change their tickers and leave through the cloneable `Subscriptions` handle returned by
+//! `QuoteGenerator::subscriptions`; these commands travel on a bounded channel and are
+//! applied between ticks. Operators can pause and resume individual tickers and read the
+//! generator's counters through `QuoteGenerator::set_paused` and `QuoteGenerator::stats`.
 //!
 //! Event model:
 //! - `QuoteEvent::Quote(Quote)` — a single quote tick.
-//! - `QuoteEvent::Shutdown` — signal for consumers to terminate gracefully.
+//! - `QuoteEvent::Book(BookMessage)` — a snapshot of or update to a ticker's order book,
+//!   delivered to the ticker's depth subscribers.
+//! - `QuoteEvent::Shutdown` — signal for consumers to terminate gracefully, broadcast once
+//!   to every subscriber by `QuoteGenerator::shutdown`.
 //!
 //! Design notes:
-//! - Uses a small random-walk around the last price to simulate movement.
+//! - Every ticker's price follows its own `PriceModel`, chosen and parameterized by the
+//!   `PriceModelConfig` in `GeneratorConfig`.
+//! - Every ticker draws from its own random generator, seeded from the generator seed and
+//!   the ticker. A ticker's n-th quote therefore depends only on the seed, its starting
+//!   price and the price model: not on thread scheduling, the number of clients, or which
+//!   other tickers are generated or paused. Without a configured seed a random one is
+//!   chosen and logged, so any run can be replayed.
+//! - Stamps every quote with a per-ticker sequence number starting at 1, so clients can
+//!   detect lost, duplicated and reordered datagrams.
 //! - Maintains last prices in a `HashMap<Ticker, f64>` so all clients observe the same
 //!   sequence of prices.
-//! - Broadcast is best-effort: if sending to a client fails, that client is removed.
+//! - Routing goes through a ticker → subscribers index, so a tick costs one channel send
+//!   per interested subscriber rather than one per subscriber and ticker.
+//! - Every ticker has a `SimulatedBook` that moves with its quotes. Depth routing is kept
+//!   apart from quote routing, so a client can follow the book of a ticker without its
+//!   quotes and vice versa. A new depth subscriber first gets a snapshot, and every
+//!   `snapshot_interval` all routed books are sent in full so that clients recover from
+//!   lost updates.
+//! - A paused ticker produces no quotes; its price and sequence number stay where they were
+//!   and continue from there when it is resumed.
+//! - Delivery never blocks the generator: a full queue applies its `OverflowPolicy`, and a
+//!   subscriber that is gone or disconnected by the policy is removed.
 
+use quote_common::ParserError;
+use quote_common::admin::{GeneratorStats, TickerStats};
+use quote_common::book::BookMessage;
 use quote_common::quote::Quote;
 use quote_common::tickers::Ticker;
-use crossbeam_channel::Sender;
-use log::info;
-use std::collections::HashMap;
-use std::thread;
-use std::time::Duration;
+use crate::metrics::metrics;
+use crate::model::order_book::{BookConfig, SimulatedBook};
+use crate::model::price_model::{PriceModel, PriceModelConfig};
+use crate::model::subscriber_queue::{Delivery, Subscriber};
+use crossbeam_channel::{Sender, bounded, select};
+use log::{error, info, warn};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Routing commands that may wait for the generator before `Subscriptions` calls block.
+const COMMAND_QUEUE_CAPACITY: usize = 1024;
 
 /// Message sent by the generator to its subscribers.
 #[derive(Clone)]
 pub enum QuoteEvent {
     /// New quote tick for a particular symbol.
     Quote(Quote),
+    /// Order book message for a ticker the subscriber follows the depth of.
+    Book(BookMessage),
     /// Global shutdown notification for all consumers.
     Shutdown,
 }
 
-/// Background market data generator that broadcasts to subscribers.
-pub struct QuoteGenerator;
+/// Identifier of a generator subscriber, unique for the lifetime of the generator.
+pub type SubscriberId = u64;
+
+/// Change to the routing index, applied by the generator thread before the next tick.
+enum RoutingCommand {
+    /// Register a subscriber for quotes of `tickers`.
+    Subscribe { subscriber: Subscriber, tickers: Vec<Ticker> },
+    /// Route quotes of `tickers` to an existing subscriber as well.
+    AddTickers { id: SubscriberId, tickers: Vec<Ticker> },
+    /// Stop routing quotes of `tickers` to a subscriber.
+    RemoveTickers { id: SubscriberId, tickers: Vec<Ticker> },
+    /// Route book messages of `tickers` to an existing subscriber.
+    AddDepth { id: SubscriberId, tickers: Vec<Ticker> },
+    /// Stop routing book messages of `tickers` to a subscriber.
+    RemoveDepth { id: SubscriberId, tickers: Vec<Ticker> },
+    /// Forget a subscriber entirely.
+    Unsubscribe { id: SubscriberId },
+}
+
+/// Operator command applied by the generator thread between ticks.
+enum ControlCommand {
+    /// Stop (`true`) or restart (`false`) generating quotes for a ticker.
+    SetPaused { ticker: Ticker, paused: bool },
+    /// Reply with the current counters.
+    Stats { reply_tx: Sender<GeneratorStats> },
+}
+
+/// Cloneable handle for subscribing to the generator's quotes by ticker.
+#[derive(Clone)]
+pub struct Subscriptions {
+    commands_tx: Sender<RoutingCommand>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl Subscriptions {
+    /// Allocate a fresh subscriber id.
+    ///
+    /// Ids are allocated separately from [`Subscriptions::subscribe`] so that the consumer
+    /// can be ready for the id before its first event is routed.
+    pub fn next_id(&self) -> SubscriberId {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Subscribe `subscriber` to quotes of `tickers`.
+    pub fn subscribe(&self, subscriber: Subscriber, tickers: &[Ticker]) -> Result<(), ParserError> {
+        self.send(RoutingCommand::Subscribe { subscriber, tickers: tickers.to_vec() })
+    }
+
+    /// Start delivering quotes of `tickers` to subscriber `id`.
+    pub fn add_tickers(&self, id: SubscriberId, tickers: &[Ticker]) -> Result<(), ParserError> {
+        self.send(RoutingCommand::AddTickers { id, tickers: tickers.to_vec() })
+    }
+
+    /// Stop delivering quotes of `tickers` to subscriber `id`.
+    pub fn remove_tickers(&self, id: SubscriberId, tickers: &[Ticker]) -> Result<(), ParserError> {
+        self.send(RoutingCommand::RemoveTickers { id, tickers: tickers.to_vec() })
+    }
+
+    /// Start delivering the order books of `tickers` to subscriber `id`, beginning with a
+    /// snapshot of each.
+    pub fn add_depth(&self, id: SubscriberId, tickers: &[Ticker]) -> Result<(), ParserError> {
+        self.send(RoutingCommand::AddDepth { id, tickers: tickers.to_vec() })
+    }
+
+    /// Stop delivering the order books of `tickers` to subscriber `id`.
+    pub fn remove_depth(&self, id: SubscriberId, tickers: &[Ticker]) -> Result<(), ParserError> {
+        self.send(RoutingCommand::RemoveDepth { id, tickers: tickers.to_vec() })
+    }
+
+    /// Stop delivering anything to subscriber `id`.
+    pub fn unsubscribe(&self, id: SubscriberId) -> Result<(), ParserError> {
+        self.send(RoutingCommand::Unsubscribe { id })
+    }
+
+    /// Routing commands waiting for the generator.
+    pub fn pending(&self) -> usize {
+        self.commands_tx.len()
+    }
+
+    fn send(&self, command: RoutingCommand) -> Result<(), ParserError> {
+        self.commands_tx
+            .send(command)
+            .map_err(|_| ParserError::ChannelSend("quote generator is not running".to_string()))
+    }
+}
+
+/// Ticker → subscribers index owned by the generator thread.
+#[derive(Default)]
+struct SubscriberIndex {
+    /// Every subscriber, used for `Shutdown` and for re-routing.
+    subscribers: HashMap<SubscriberId, Subscriber>,
+    /// Subscribers of each ticker.
+    routes: HashMap<Ticker, Vec<Subscriber>>,
+    /// Depth subscribers of each ticker.
+    depth_routes: HashMap<Ticker, Vec<Subscriber>>,
+}
+
+impl SubscriberIndex {
+    /// Apply `command`, returning the tickers whose books were newly routed to a subscriber
+    /// and are owed a snapshot.
+    fn apply(&mut self, command: RoutingCommand) -> Option<(SubscriberId, Vec<Ticker>)> {
+        let mut added_depth = None;
+        match command {
+            RoutingCommand::Subscribe { subscriber, tickers } => {
+                let id = subscriber.id();
+                self.subscribers.insert(id, subscriber);
+                self.add(id, &tickers, false);
+                info!("Generator: subscriber {} added. Total subscribers: {}", id, self.subscribers.len());
+            }
+            RoutingCommand::AddTickers { id, tickers } => {
+                self.add(id, &tickers, false);
+            }
+            RoutingCommand::RemoveTickers { id, tickers } => {
+                for ticker in &tickers {
+                    self.unroute(ticker, id, false);
+                }
+            }
+            RoutingCommand::AddDepth { id, tickers } => {
+                added_depth = Some((id, self.add(id, &tickers, true)));
+            }
+            RoutingCommand::RemoveDepth { id, tickers } => {
+                for ticker in &tickers {
+                    self.unroute(ticker, id, true);
+                }
+            }
+            RoutingCommand::Unsubscribe { id } => self.remove(id),
+        }
+        self.publish();
+        added_depth
+    }
+
+    /// Report the subscriber count of every routed ticker to the metrics.
+    fn publish(&self) {
+        metrics().set_ticker_subscribers(self.routes.iter().map(|(ticker, route)| (ticker, route.len())));
+    }
+
+    /// Quote routes, or depth routes if `depth`.
+    fn routes_mut(&mut self, depth: bool) -> &mut HashMap<Ticker, Vec<Subscriber>> {
+        if depth { &mut self.depth_routes } else { &mut self.routes }
+    }
+
+    /// Route `tickers` to subscriber `id`, returning the tickers it was not routed yet.
+    fn add(&mut self, id: SubscriberId, tickers: &[Ticker], depth: bool) -> Vec<Ticker> {
+        let Some(subscriber) = self.subscribers.get(&id).cloned() else {
+            return Vec::new();
+        };
+        let routes = self.routes_mut(depth);
+        let mut added = Vec::new();
+        for ticker in tickers {
+            let route = routes.entry(ticker.clone()).or_default();
+            if !route.iter().any(|existing| existing.id() == id) {
+                route.push(subscriber.clone());
+                added.push(ticker.clone());
+            }
+        }
+        added
+    }
+
+    fn unroute(&mut self, ticker: &Ticker, id: SubscriberId, depth: bool) {
+        let routes = self.routes_mut(depth);
+        if let Some(route) = routes.get_mut(ticker) {
+            route.retain(|existing| existing.id() != id);
+            if route.is_empty() {
+                routes.remove(ticker);
+            }
+        }
+    }
+
+    fn remove(&mut self, id: SubscriberId) {
+        if self.subscribers.remove(&id).is_some() {
+            for routes in [&mut self.routes, &mut self.depth_routes] {
+                routes.retain(|_, route| {
+                    route.retain(|existing| existing.id() != id);
+                    !route.is_empty()
+                });
+            }
+            info!("Generator: subscriber {} removed. Total subscribers: {}", id, self.subscribers.len());
+        }
+    }
+
+    /// Deliver `quote` to the subscribers of its ticker, dropping those that are gone or
+    /// were disconnected by their overflow policy.
+    fn route(&mut self, ticker: &Ticker, quote: Quote) {
+        let route = self.routes.get(ticker).map_or(&[][..], Vec::as_slice);
+        let removed = Self::deliver(route, QuoteEvent::Quote(quote));
+        self.remove_all(removed);
+    }
+
+    /// Deliver `message` to the depth subscribers of `ticker`.
+    fn route_book(&mut self, ticker: &Ticker, message: BookMessage) {
+        let route = self.depth_routes.get(ticker).map_or(&[][..], Vec::as_slice);
+        let removed = Self::deliver(route, QuoteEvent::Book(message));
+        self.remove_all(removed);
+    }
+
+    /// Deliver `message` to subscriber `id` alone.
+    fn send_book(&mut self, id: SubscriberId, message: BookMessage) {
+        let Some(subscriber) = self.subscribers.get(&id) else {
+            return;
+        };
+        let removed = Self::deliver(std::slice::from_ref(subscriber), QuoteEvent::Book(message));
+        self.remove_all(removed);
+    }
+
+    /// Deliver `event` to every subscriber of `route`, returning those to remove.
+    fn deliver(route: &[Subscriber], event: QuoteEvent) -> Vec<SubscriberId> {
+        let mut removed = Vec::new();
+        for subscriber in route {
+            match subscriber.deliver(event.clone()) {
+                Delivery::Queued | Delivery::Dropped => {}
+                Delivery::Disconnected => {
+                    warn!("Generator: subscriber {} is too slow; disconnecting it", subscriber.id());
+                    removed.push(subscriber.id());
+                }
+                Delivery::Gone => removed.push(subscriber.id()),
+            }
+        }
+        removed
+    }
+
+    fn remove_all(&mut self, removed: Vec<SubscriberId>) {
+        if !removed.is_empty() {
+            for id in removed {
+                self.remove(id);
+            }
+            self.publish();
+        }
+    }
+
+    /// Number of subscribers the quotes of `ticker` are routed to.
+    fn route_len(&self, ticker: &Ticker) -> usize {
+        self.routes.get(ticker).map_or(0, Vec::len)
+    }
+
+    /// Deliver `Shutdown` once to every subscriber.
+    fn shutdown(&self) {
+        info!("Generator: broadcasting shutdown to {} subscribers", self.subscribers.len());
+        for subscriber in self.subscribers.values() {
+            subscriber.deliver(QuoteEvent::Shutdown);
+        }
+    }
+}
+
+/// What the generator produces and how often.
+#[derive(Debug, Clone)]
+pub struct GeneratorConfig {
+    /// Tickers for which the generator produces quotes.
+    pub tickers: Vec<Ticker>,
+    /// Starting price of every ticker.
+    pub initial_prices: HashMap<Ticker, f64>,
+    /// Interval between ticks; each tick produces one quote per ticker.
+    pub tick_interval: Duration,
+    /// Price process of every ticker.
+    pub price_model: PriceModelConfig,
+    /// Seed of the price paths; a random seed if `None`.
+    pub seed: Option<u64>,
+    /// Depth and snapshot interval of the order books.
+    pub book: BookConfig,
+}
+
+/// Price process and order book of one ticker, and the random source they draw from.
+struct PricePath {
+    model: Box<dyn PriceModel>,
+    book: SimulatedBook,
+    rng: StdRng,
+}
+
+impl PricePath {
+    fn new(model: Box<dyn PriceModel>, book: SimulatedBook, seed: u64, ticker: &Ticker) -> Self {
+        // Mix the ticker id into the seed so that every ticker gets an independent stream.
+        let stream = u64::from(ticker.id()).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        Self { model, book, rng: StdRng::seed_from_u64(seed ^ stream) }
+    }
+}
+
+/// Prices and counters owned by the generator thread.
+struct Market {
+    tickers: Vec<Ticker>,
+    prices: HashMap<Ticker, f64>,
+    paths: HashMap<Ticker, PricePath>,
+    sequences: HashMap<Ticker, u64>,
+    paused: HashSet<Ticker>,
+    tick_interval: Duration,
+    snapshot_interval: Duration,
+    last_snapshot: Instant,
+    started_at: Instant,
+    ticks: u64,
+    quotes: u64,
+}
+
+impl Market {
+    fn new(config: GeneratorConfig) -> Self {
+        let GeneratorConfig { tickers, initial_prices, tick_interval, price_model, seed, book } = config;
+        let seed = seed.unwrap_or_else(rand::random);
+        info!("Generator: seed {} (start with --seed {} to replay this run)", seed, seed);
+        price_model.log();
+        let paths = initial_prices
+            .iter()
+            .map(|(ticker, &price)| {
+                let model = price_model.build(ticker, price, tick_interval);
+                let levels = SimulatedBook::new(ticker, book.levels);
+                (ticker.clone(), PricePath::new(model, levels, seed, ticker))
+            })
+            .collect();
+        Self {
+            tickers,
+            prices: initial_prices,
+            paths,
+            sequences: HashMap::new(),
+            paused: HashSet::new(),
+            tick_interval,
+            snapshot_interval: book.snapshot_interval,
+            last_snapshot: Instant::now(),
+            started_at: Instant::now(),
+            ticks: 0,
+            quotes: 0,
+        }
+    }
+
+    /// Generate one quote for every ticker that is not paused and route it.
+    fn tick(&mut self, index: &mut SubscriberIndex) {
+        self.ticks += 1;
+        let generated = self.quotes;
+        for ticker in &self.tickers {
+            if self.paused.contains(ticker) {
+                continue;
+            }
+            let (Some(current_price), Some(path)) = (self.prices.get_mut(ticker), self.paths.get_mut(ticker)) else {
+                continue;
+            };
+
+            *current_price = path.model.next_price(*current_price, &mut path.rng);
+            let mut quote = Quote::synthesize(ticker, *current_price, &mut path.rng);
+            let seq = self.sequences.entry(ticker.clone()).or_insert(0);
+            *seq += 1;
+            quote.seq = *seq;
+            self.quotes += 1;
+            let updates = path.book.advance(&quote, &mut path.rng);
+
+            index.route(ticker, quote);
+            for update in updates {
+                index.route_book(ticker, update);
+            }
+        }
+        metrics().quotes_generated(self.quotes - generated);
+
+        if self.last_snapshot.elapsed() >= self.snapshot_interval {
+            self.last_snapshot = Instant::now();
+            for ticker in &self.tickers {
+                if index.depth_routes.contains_key(ticker)
+                    && let Some(path) = self.paths.get(ticker)
+                {
+                    index.route_book(ticker, path.book.snapshot());
+                }
+            }
+        }
+    }
+
+    /// Send subscriber `id` a snapshot of the books of `tickers`.
+    fn send_snapshots(&self, index: &mut SubscriberIndex, id: SubscriberId, tickers: &[Ticker]) {
+        for ticker in tickers {
+            if let Some(path) = self.paths.get(ticker) {
+                index.send_book(id, path.book.snapshot());
+            }
+        }
+    }
+
+    fn apply(&mut self, command: ControlCommand, index: &SubscriberIndex) {
+        match command {
+            ControlCommand::SetPaused { ticker, paused } => {
+                let changed = if paused {
+                    self.paused.insert(ticker.clone())
+                } else {
+                    self.paused.remove(&ticker)
+                };
+                if changed {
+                    info!("Generator: {} {}", if paused { "paused" } else { "resumed" }, ticker);
+                }
+            }
+            ControlCommand::Stats { reply_tx } => {
+                let _ = reply_tx.send(self.stats(index));
+            }
+        }
+    }
+
+    fn stats(&self, index: &SubscriberIndex) -> GeneratorStats {
+        GeneratorStats {
+            uptime_ms: self.started_at.elapsed().as_millis() as u64,
+            tick_interval_ms: self.tick_interval.as_millis() as u64,
+            ticks: self.ticks,
+            quotes: self.quotes,
+            subscribers: index.subscribers.len(),
+            tickers: self
+                .tickers
+                .iter()
+                .map(|ticker| TickerStats {
+                    ticker: ticker.clone(),
+                    price: self.prices.get(ticker).copied().unwrap_or_default(),
+                    seq: self.sequences.get(ticker).copied().unwrap_or_default(),
+                    subscribers: index.route_len(ticker),
+                    paused: self.paused.contains(ticker),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Handle to the background market data generator.
+pub struct QuoteGenerator {
+    subscriptions: Subscriptions,
+    control_tx: Sender<ControlCommand>,
+    stop_tx: Sender<()>,
+    thread: JoinHandle<()>,
+}
 
 impl QuoteGenerator {
-    /// Start the generator thread and return a channel for registering subscribers.
+    /// Start the generator thread.
     ///
-    /// The returned `Sender<Sender<QuoteEvent>>` accepts a per-subscriber channel; the
-    /// generator will push every `QuoteEvent` to all registered channels. If a send fails,
-    /// the corresponding subscriber is dropped from the list.
-    pub fn start() -> Sender<Sender<QuoteEvent>> {
-        let (subscribe_tx, subscribe_rx) = crossbeam_channel::unbounded::<Sender<QuoteEvent>>();
-
-        thread::spawn(move || {
-            let mut clients: Vec<Sender<QuoteEvent>> = Vec::new();
-            let tickers = vec![Ticker::AAPL, Ticker::MSFT, Ticker::TSLA, Ticker::GOOGL];
-            
-            let initial_price = 100.0;
-            let mut current_prices: HashMap<Ticker, f64> =
-                tickers.iter().map(|t| (t.clone(), initial_price)).collect();
+    /// Subscribers register through [`QuoteGenerator::subscriptions`] and receive only the
+    /// quotes of the tickers they subscribed to.
+    pub fn start(config: GeneratorConfig) -> Self {
+        let (commands_tx, commands_rx) = bounded::<RoutingCommand>(COMMAND_QUEUE_CAPACITY);
+        let (control_tx, control_rx) = bounded::<ControlCommand>(COMMAND_QUEUE_CAPACITY);
+        let (stop_tx, stop_rx) = bounded::<()>(1);
+
+        let thread = thread::spawn(move || {
+            let mut index = SubscriberIndex::default();
+            let mut market = Market::new(config);
+            let mut next_tick = Instant::now();
 
             info!(
                 "Market Generator started (Thread ID: {:?})",
@@ -58,28 +508,71 @@ impl QuoteGenerator {
             );
 
             loop {
-                while let Ok(new_client_tx) = subscribe_rx.try_recv() {
-                    clients.push(new_client_tx);
-                    info!(
-                        "Generator: New client added. Total clients: {}",
-                        clients.len()
-                    );
+                let now = Instant::now();
+                if now >= next_tick {
+                    market.tick(&mut index);
+                    next_tick = now + market.tick_interval;
+                    continue;
                 }
-                
-                for ticker in &tickers {
-                    let current_price = *current_prices.get(ticker).unwrap_or(&initial_price);
-
-                    if let Ok(quote) = Quote::generate_new(ticker, current_price) {
-                        current_prices.insert(ticker.clone(), quote.price);
 
-                        let event = QuoteEvent::Quote(quote);
-                        clients.retain(|client_tx| client_tx.send(event.clone()).is_ok());
-                    }
+                select! {
+                    recv(commands_rx) -> command => match command {
+                        Ok(command) => {
+                            if let Some((id, tickers)) = index.apply(command) {
+                                market.send_snapshots(&mut index, id, &tickers);
+                            }
+                        }
+                        Err(_) => break,
+                    },
+                    recv(control_rx) -> command => if let Ok(command) = command {
+                        market.apply(command, &index);
+                    },
+                    recv(stop_rx) -> _ => break,
+                    default(next_tick - now) => {},
                 }
-                
-                thread::sleep(Duration::from_millis(500));
             }
+
+            while let Ok(command) = commands_rx.try_recv() {
+                let _ = index.apply(command);
+            }
+            index.shutdown();
         });
-        subscribe_tx
+        let subscriptions = Subscriptions {
+            commands_tx,
+            next_id: Arc::new(AtomicU64::new(1)),
+        };
+        Self { subscriptions, control_tx, stop_tx, thread }
+    }
+
+    /// Handle for subscribing to quotes.
+    pub fn subscriptions(&self) -> Subscriptions {
+        self.subscriptions.clone()
+    }
+
+    /// Stop (`paused = true`) or restart generating quotes for `ticker`.
+    pub fn set_paused(&self, ticker: &Ticker, paused: bool) -> Result<(), ParserError> {
+        self.control_tx
+            .send(ControlCommand::SetPaused { ticker: ticker.clone(), paused })
+            .map_err(|_| ParserError::ChannelSend("quote generator is not running".to_string()))
+    }
+
+    /// Current counters and per-ticker state, read between ticks.
+    pub fn stats(&self) -> Result<GeneratorStats, ParserError> {
+        let (reply_tx, reply_rx) = bounded::<GeneratorStats>(1);
+        self.control_tx
+            .send(ControlCommand::Stats { reply_tx })
+            .map_err(|_| ParserError::ChannelSend("quote generator is not running".to_string()))?;
+        reply_rx
+            .recv()
+            .map_err(|_| ParserError::ChannelRecv("quote generator is not running".to_string()))
+    }
+
+    /// Stop generating, broadcast `QuoteEvent::Shutdown` to every subscriber and wait for
+    /// the generator thread to finish.
+    pub fn shutdown(self) {
+        let _ = self.stop_tx.send(());
+        if self.thread.join().is_err() {
+            error!("Generator thread panicked");
+        }
     }
 }
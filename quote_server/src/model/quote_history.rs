@@ -0,0 +1,95 @@
+//! Bounded per-ticker history of recently generated quotes.
+//!
+//! `QuoteHistory` subscribes to every ticker of the `QuoteGenerator` and keeps the
+//! last `capacity` quotes of every ticker in a ring buffer. Clients that detect a sequence
+//! gap send a `RETRANSMIT` request on the command channel, and the server answers it from
+//! this history. Quotes older than the buffer are no longer recoverable.
+
+use crate::model::quote_generator::{QuoteEvent, Subscriptions};
+use crate::model::subscriber_queue::{OverflowPolicy, QueueConfig, Subscriber, SubscriberQueue};
+use crossbeam_channel::unbounded;
+use log::{error, info, warn};
+use quote_common::ParserError;
+use quote_common::quote::Quote;
+use quote_common::tickers::Ticker;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Default number of quotes kept per ticker.
+pub const HISTORY_CAPACITY: usize = 1024;
+/// Quotes that may wait for the recorder before the oldest are dropped.
+const RECORDER_QUEUE: QueueConfig = QueueConfig { capacity: 16 * 1024, policy: OverflowPolicy::DropOldest };
+
+/// Shared, bounded per-ticker ring buffer of recent quotes.
+#[derive(Clone)]
+pub struct QuoteHistory {
+    buffers: Arc<Mutex<HashMap<String, VecDeque<Quote>>>>,
+    capacity: usize,
+}
+
+impl QuoteHistory {
+    /// Subscribe a recorder to `tickers` and return the history it fills.
+    ///
+    /// The recorder runs on its own thread until the generator shuts down.
+    pub fn start(subscriptions: &Subscriptions, tickers: &[Ticker], capacity: usize) -> Result<Self, ParserError> {
+        let history = Self {
+            buffers: Arc::new(Mutex::new(HashMap::new())),
+            capacity,
+        };
+        let queue = Arc::new(SubscriberQueue::new(RECORDER_QUEUE));
+        let (ready_tx, ready_rx) = unbounded();
+        let subscriber = Subscriber::new(subscriptions.next_id(), Arc::clone(&queue), ready_tx);
+        subscriptions.subscribe(subscriber, tickers)?;
+
+        let recorder = history.clone();
+        thread::spawn(move || {
+            'record: for _ in ready_rx {
+                for event in queue.drain().events {
+                    match event {
+                        QuoteEvent::Quote(quote) => {
+                            if let Err(e) = recorder.record(quote) {
+                                error!("Quote history recorder failed: {}", e);
+                                break 'record;
+                            }
+                        }
+                        QuoteEvent::Book(_) => {}
+                        QuoteEvent::Shutdown => break 'record,
+                    }
+                }
+            }
+            if queue.dropped() > 0 {
+                warn!("Quote history recorder fell behind and missed {} quotes", queue.dropped());
+            }
+            info!("Quote history recorder stopped");
+        });
+        Ok(history)
+    }
+
+    /// Append `quote` to its ticker's buffer, evicting the oldest entry when full.
+    fn record(&self, quote: Quote) -> Result<(), ParserError> {
+        let mut buffers = self.buffers.lock()?;
+        let buffer = buffers.entry(quote.ticker.clone()).or_default();
+        if buffer.len() == self.capacity {
+            buffer.pop_front();
+        }
+        buffer.push_back(quote);
+        Ok(())
+    }
+
+    /// Quotes of `ticker` with sequence numbers in `from_seq..=to_seq` that are still
+    /// buffered, in sequence order.
+    pub fn range(&self, ticker: &str, from_seq: u64, to_seq: u64) -> Result<Vec<Quote>, ParserError> {
+        let buffers = self.buffers.lock()?;
+        Ok(buffers
+            .get(ticker)
+            .map(|buffer| {
+                buffer
+                    .iter()
+                    .filter(|q| q.seq >= from_seq && q.seq <= to_seq)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+}
@@ -0,0 +1,383 @@
+//! Fixed pool of stream workers that fan quotes out to clients.
+//!
+//! Instead of one thread per subscription, the server runs a fixed number of workers.
+//! Every client's `ClientStream` is owned by exactly one worker, chosen by its subscriber
+//! id. The generator fills the client's bounded `SubscriberQueue` and wakes the worker by
+//! sending the id on the worker's ready channel. The thread count therefore stays constant
+//! however many clients subscribe, and a slow client only fills its own queue.
+//!
+//! Each worker runs a small event loop over two channels:
+//!
+//! - control — `Open`/`Close`/`Kick` of streams and the final `Stop`;
+//! - ready — ids of streams whose queue has events; it holds at most one id per stream.
+//!
+//! Deadlines of pending batches and conflation intervals are tracked in a heap so that a
+//! worker only touches streams whose quotes are actually due. A stream whose send fails,
+//! or whose queue overflowed under `OverflowPolicy::Disconnect`, is dropped by its worker
+//! and reported on the pool's `closed_tx` so the registry can forget it. Quotes discarded
+//! by the overflow policy are logged per client at most every `DROP_REPORT_INTERVAL`, and
+//! once more when the stream ends.
+
+use crate::model::client_stream::ClientStream;
+use crate::model::quote_generator::{QuoteEvent, SubscriberId};
+use crate::model::subscriber_queue::SubscriberQueue;
+use crossbeam_channel::{Receiver, Sender, bounded, select, unbounded};
+use log::{error, info, warn};
+use quote_common::ParserError;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How long a worker waits for events when no batch is pending.
+const IDLE_WAIT: Duration = Duration::from_secs(1);
+/// Control messages that may wait for a worker before `StreamPool` calls block.
+const CONTROL_QUEUE_CAPACITY: usize = 1024;
+/// Shortest interval between two drop reports for the same client.
+const DROP_REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Change to the set of streams owned by a worker.
+enum WorkerControl {
+    /// Start delivering the events of `queue` to `stream`.
+    Open {
+        id: SubscriberId,
+        stream: Box<ClientStream>,
+        queue: Arc<SubscriberQueue>,
+    },
+    /// Flush and drop the stream of `id`.
+    Close { id: SubscriberId },
+    /// Drop the stream of `id` without flushing, telling the client `reason`.
+    Kick { id: SubscriberId, reason: String },
+    /// Deliver the remaining events and exit.
+    Stop,
+}
+
+/// Handle to one worker thread.
+struct Worker {
+    control_tx: Sender<WorkerControl>,
+    ready_tx: Sender<SubscriberId>,
+    thread: JoinHandle<()>,
+}
+
+/// Fixed set of worker threads that own all client streams.
+pub struct StreamPool {
+    workers: Vec<Worker>,
+}
+
+impl StreamPool {
+    /// Start `size` workers (at least one).
+    ///
+    /// Ids of streams that a worker drops on its own — after a send failure or a
+    /// disconnecting overflow — are sent on `closed_tx`.
+    pub fn start(size: usize, closed_tx: Sender<SubscriberId>) -> Self {
+        let workers = (0..size.max(1))
+            .map(|index| {
+                let (control_tx, control_rx) = bounded::<WorkerControl>(CONTROL_QUEUE_CAPACITY);
+                let (ready_tx, ready_rx) = unbounded::<SubscriberId>();
+                let closed_tx = closed_tx.clone();
+                let thread = thread::Builder::new()
+                    .name(format!("stream-worker-{}", index))
+                    .spawn(move || WorkerLoop::new(closed_tx).run(control_rx, ready_rx))
+                    .expect("failed to spawn stream worker");
+                Worker { control_tx, ready_tx, thread }
+            })
+            .collect::<Vec<_>>();
+        info!("Stream pool started with {} workers", workers.len());
+        Self { workers }
+    }
+
+    /// Number of worker threads.
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Channel that wakes the worker responsible for `id`.
+    pub fn ready_tx(&self, id: SubscriberId) -> Sender<SubscriberId> {
+        self.worker(id).ready_tx.clone()
+    }
+
+    /// Hand `stream`, fed from `queue`, to the worker responsible for `id`.
+    ///
+    /// Must be called before `id` is first sent on [`StreamPool::ready_tx`].
+    pub fn open(
+        &self,
+        id: SubscriberId,
+        stream: ClientStream,
+        queue: Arc<SubscriberQueue>,
+    ) -> Result<(), ParserError> {
+        self.worker(id)
+            .control_tx
+            .send(WorkerControl::Open { id, stream: Box::new(stream), queue })
+            .map_err(|_| ParserError::ChannelSend("stream worker is not running".to_string()))
+    }
+
+    /// Flush and drop the stream of `id`.
+    pub fn close(&self, id: SubscriberId) {
+        let _ = self.worker(id).control_tx.send(WorkerControl::Close { id });
+    }
+
+    /// Drop the stream of `id` without sending its pending quotes, after sending the client
+    /// a going-away notice with `reason`.
+    pub fn kick(&self, id: SubscriberId, reason: &str) {
+        let _ = self
+            .worker(id)
+            .control_tx
+            .send(WorkerControl::Kick { id, reason: reason.to_string() });
+    }
+
+    /// Stop every worker once it has delivered the events already queued for it, and wait
+    /// for the threads to finish.
+    pub fn shutdown(self) {
+        for worker in &self.workers {
+            let _ = worker.control_tx.send(WorkerControl::Stop);
+        }
+        for worker in self.workers {
+            if worker.thread.join().is_err() {
+                error!("Stream worker panicked");
+            }
+        }
+    }
+
+    fn worker(&self, id: SubscriberId) -> &Worker {
+        &self.workers[(id % self.workers.len() as u64) as usize]
+    }
+}
+
+/// A stream owned by a worker, with its queue and drop-report state.
+struct Entry {
+    stream: ClientStream,
+    queue: Arc<SubscriberQueue>,
+    /// Drop count at the last report.
+    reported_drops: u64,
+    /// When drops were last reported.
+    reported_at: Option<Instant>,
+}
+
+impl Entry {
+    /// Log quotes dropped since the last report, unless one was logged recently and
+    /// `force` is false.
+    fn report_drops(&mut self, force: bool) {
+        let dropped = self.queue.dropped();
+        if dropped == self.reported_drops
+            || !force && self.reported_at.is_some_and(|at| at.elapsed() < DROP_REPORT_INTERVAL)
+        {
+            return;
+        }
+        warn!(
+            "Stream {}: dropped {} quotes under the {} policy ({} in total)",
+            self.stream.target_addr(),
+            dropped - self.reported_drops,
+            self.queue.config().policy,
+            dropped
+        );
+        self.reported_drops = dropped;
+        self.reported_at = Some(Instant::now());
+    }
+}
+
+/// State of one worker thread.
+struct WorkerLoop {
+    streams: HashMap<SubscriberId, Entry>,
+    /// Stream deadlines, earliest first. Entries of streams that were flushed or closed in
+    /// the meantime are skipped when popped.
+    deadlines: BinaryHeap<Reverse<(Instant, SubscriberId)>>,
+    closed_tx: Sender<SubscriberId>,
+}
+
+impl WorkerLoop {
+    fn new(closed_tx: Sender<SubscriberId>) -> Self {
+        Self {
+            streams: HashMap::new(),
+            deadlines: BinaryHeap::new(),
+            closed_tx,
+        }
+    }
+
+    fn run(mut self, control_rx: Receiver<WorkerControl>, ready_rx: Receiver<SubscriberId>) {
+        loop {
+            select! {
+                recv(control_rx) -> msg => match msg {
+                    Ok(control) => {
+                        if !self.apply(control) {
+                            break;
+                        }
+                    }
+                    Err(_) => break,
+                },
+                recv(ready_rx) -> msg => match msg {
+                    Ok(id) => {
+                        if !self.streams.contains_key(&id) {
+                            // The stream was opened before it was first woken, but the
+                            // control message may not have been picked up yet.
+                            while let Ok(control) = control_rx.try_recv() {
+                                if !self.apply(control) {
+                                    return self.finish(&ready_rx);
+                                }
+                            }
+                        }
+                        self.deliver(id);
+                    }
+                    Err(_) => break,
+                },
+                default(self.wait_time()) => {},
+            }
+            if ready_rx.is_empty() {
+                self.flush_due(Instant::now());
+            }
+        }
+        self.finish(&ready_rx);
+    }
+
+    /// Apply a control message; returns `false` on `Stop`.
+    fn apply(&mut self, control: WorkerControl) -> bool {
+        match control {
+            WorkerControl::Open { id, stream, queue } => {
+                let entry = Entry { stream: *stream, queue, reported_drops: 0, reported_at: None };
+                if let Some(mut previous) = self.streams.insert(id, entry) {
+                    warn!("Stream {} replaced an open stream with the same id", previous.stream.target_addr());
+                    let _ = previous.stream.flush();
+                }
+            }
+            WorkerControl::Close { id } => {
+                if let Some(mut entry) = self.streams.remove(&id) {
+                    entry.report_drops(true);
+                    if let Err(e) = entry.stream.flush() {
+                        error!("Failed to flush stream {}: {}", entry.stream.target_addr(), e);
+                    }
+                }
+            }
+            WorkerControl::Kick { id, reason } => {
+                if let Some(mut entry) = self.streams.remove(&id) {
+                    entry.report_drops(true);
+                    entry.stream.disconnect(&reason);
+                    info!("Stream {}: kicked ({})", entry.stream.target_addr(), reason);
+                }
+            }
+            WorkerControl::Stop => return false,
+        }
+        true
+    }
+
+    /// Send the queued events of stream `id`.
+    fn deliver(&mut self, id: SubscriberId) {
+        let Some(entry) = self.streams.get_mut(&id) else {
+            return;
+        };
+        let drained = entry.queue.drain();
+        for event in drained.events {
+            if !self.handle(id, event) {
+                return;
+            }
+        }
+        if drained.disconnected {
+            self.disconnect(id);
+        } else if let Some(entry) = self.streams.get_mut(&id) {
+            entry.report_drops(false);
+        }
+    }
+
+    /// Deliver one event to stream `id`. Returns `false` once the stream is gone.
+    fn handle(&mut self, id: SubscriberId, event: QuoteEvent) -> bool {
+        let Some(entry) = self.streams.get_mut(&id) else {
+            return false;
+        };
+        match event {
+            QuoteEvent::Quote(quote) => {
+                let pending = entry.stream.deadline();
+                if let Err(e) = entry.stream.push(quote) {
+                    error!("Failed to send quotes to {}: {}", entry.stream.target_addr(), e);
+                    self.drop_stream(id);
+                    return false;
+                }
+                if let Some(deadline) = entry.stream.deadline()
+                    && pending != Some(deadline)
+                {
+                    self.deadlines.push(Reverse((deadline, id)));
+                }
+                true
+            }
+            QuoteEvent::Book(message) => {
+                if let Err(e) = entry.stream.push_book(&message) {
+                    error!("Failed to send book to {}: {}", entry.stream.target_addr(), e);
+                    self.drop_stream(id);
+                    return false;
+                }
+                true
+            }
+            QuoteEvent::Shutdown => {
+                entry.report_drops(true);
+                if let Err(e) = entry.stream.going_away("server is shutting down") {
+                    error!("Failed to notify {} of shutdown: {}", entry.stream.target_addr(), e);
+                }
+                self.streams.remove(&id);
+                false
+            }
+        }
+    }
+
+    /// Drop stream `id` after its queue overflowed under `OverflowPolicy::Disconnect`.
+    fn disconnect(&mut self, id: SubscriberId) {
+        let Some(entry) = self.streams.get_mut(&id) else {
+            return;
+        };
+        let capacity = entry.queue.config().capacity;
+        warn!(
+            "Stream {}: more than {} quotes queued; disconnecting slow client",
+            entry.stream.target_addr(),
+            capacity
+        );
+        entry.stream.disconnect(&format!("client fell more than {} quotes behind", capacity));
+        self.drop_stream(id);
+    }
+
+    /// Forget stream `id` and report it to the registry.
+    fn drop_stream(&mut self, id: SubscriberId) {
+        if let Some(mut entry) = self.streams.remove(&id) {
+            entry.report_drops(true);
+            let _ = self.closed_tx.send(id);
+        }
+    }
+
+    /// Send every batch whose deadline has passed.
+    fn flush_due(&mut self, now: Instant) {
+        while let Some(Reverse((deadline, id))) = self.deadlines.peek().copied()
+            && deadline <= now
+        {
+            self.deadlines.pop();
+            let Some(entry) = self.streams.get_mut(&id) else {
+                continue;
+            };
+            if let Err(e) = entry.stream.flush_if_due(now) {
+                error!("Failed to send quotes to {}: {}", entry.stream.target_addr(), e);
+                self.drop_stream(id);
+            } else if let Some(next) = entry.stream.deadline()
+                && next > now
+            {
+                self.deadlines.push(Reverse((next, id)));
+            }
+        }
+    }
+
+    /// How long to wait before the earliest pending batch is due.
+    fn wait_time(&self) -> Duration {
+        self.deadlines
+            .peek()
+            .map(|Reverse((deadline, _))| deadline.saturating_duration_since(Instant::now()))
+            .unwrap_or(IDLE_WAIT)
+    }
+
+    /// Deliver the events that are already queued, then flush every stream.
+    fn finish(mut self, ready_rx: &Receiver<SubscriberId>) {
+        while let Ok(id) = ready_rx.try_recv() {
+            self.deliver(id);
+        }
+        for entry in self.streams.values_mut() {
+            entry.report_drops(true);
+            if let Err(e) = entry.stream.flush() {
+                error!("Failed to flush stream {}: {}", entry.stream.target_addr(), e);
+            }
+            entry.stream.drain();
+        }
+    }
+}
@@ -0,0 +1,432 @@
+//! Registry of live client streams.
+//!
+//! The `StreamRegistry` is owned by the server's main loop. It turns client commands into
+//! changes of the running stream tasks:
+//!
+//! - `subscribe` — validates the tickers, hands a `ClientStream` for the client to the
+//!   `StreamPool` and subscribes it to the tickers and depth tickers with the generator. A
+//!   re-subscription from the same address replaces the client's previous stream.
+//! - `update_tickers` / `update_depth` — adds or removes quote or depth tickers in the
+//!   generator's routing for the existing stream without restarting it.
+//! - `unsubscribe` / `close` — removes the stream from the routing and the pool and forgets
+//!   the client, including its entry in the `PingMonitor`.
+//! - `kick` — like `close`, but tells the client why and does not send its pending quotes.
+//! - `clients` — describes every live stream for the admin interface.
+//! - `forget` — drops the bookkeeping of a stream that the pool closed on its own.
+//! - `shutdown` — stops the pool once the generator has broadcast `Shutdown`.
+//!
+//! UDP streams are keyed by the client's UDP `SocketAddr`, which is also the address that the
+//! ping monitor reports on timeout. TCP streams are keyed by the peer address of the
+//! command connection they are written to.
+
+use crate::metrics::metrics;
+use crate::model::client_stream::{ClientStream, QuoteSink, StreamOptions, TcpWriter};
+use crate::model::ping_monitor::PingMonitor;
+use crate::model::quote_generator::{SubscriberId, Subscriptions};
+use crate::model::stream_pool::StreamPool;
+use crate::model::subscriber_queue::{Subscriber, SubscriberQueue};
+use log::{error, info, warn};
+use quote_common::admin::ClientInfo;
+use quote_common::command::{RejectReason, RejectedTicker, Response, Transport};
+use quote_common::tickers::Ticker;
+use std::collections::HashMap;
+use std::net::{SocketAddr, TcpStream, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Server version reported to clients in every `Response`.
+pub const SERVER_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Main-loop bookkeeping for a single running client stream.
+struct ActiveStream {
+    /// Identifier reported to the client in `Response::subscription_id`.
+    subscription_id: u64,
+    /// Tickers currently streamed to the client.
+    tickers: Vec<Ticker>,
+    /// Tickers whose order books are streamed to the client.
+    depth: Vec<Ticker>,
+    /// Session and encoding the stream was created with.
+    options: StreamOptions,
+    /// Generator subscriber id, which also identifies the stream in the pool.
+    subscriber_id: SubscriberId,
+    /// Transport the quotes are sent over.
+    transport: Transport,
+    /// Queue feeding the stream, read for its drop count.
+    queue: Arc<SubscriberQueue>,
+    /// When the stream was created.
+    created_at: Instant,
+}
+
+impl ActiveStream {
+    /// Response describing the stream.
+    fn response(&self, rejected: Vec<RejectedTicker>, error: Option<String>) -> Response {
+        Response {
+            depth: self.depth.clone(),
+            ..response(self.tickers.clone(), rejected, Some(self.subscription_id), error)
+        }
+    }
+
+    /// Whether the stream has neither quote nor depth tickers left.
+    fn is_empty(&self) -> bool {
+        self.tickers.is_empty() && self.depth.is_empty()
+    }
+}
+
+/// Owns the set of active client streams and applies client commands to them.
+pub struct StreamRegistry {
+    socket: Arc<UdpSocket>,
+    subscriptions: Subscriptions,
+    pool: StreamPool,
+    /// Keep-alive state of UDP clients, cleared when their stream ends.
+    ping_monitor: Arc<Mutex<PingMonitor>>,
+    /// Tickers the generator produces quotes for.
+    supported: Vec<Ticker>,
+    active_streams: HashMap<SocketAddr, ActiveStream>,
+    next_subscription_id: u64,
+}
+
+impl StreamRegistry {
+    /// Create an empty registry that streams over `socket` from the workers of `pool` and
+    /// subscribes new clients with the generator through `subscriptions`. Only `supported`
+    /// tickers are accepted. Clients whose stream ends are removed from `ping_monitor`.
+    pub fn new(
+        socket: Arc<UdpSocket>,
+        subscriptions: Subscriptions,
+        pool: StreamPool,
+        ping_monitor: Arc<Mutex<PingMonitor>>,
+        supported: Vec<Ticker>,
+    ) -> Self {
+        Self {
+            socket,
+            subscriptions,
+            pool,
+            ping_monitor,
+            supported,
+            active_streams: HashMap::new(),
+            next_subscription_id: 0,
+        }
+    }
+
+    /// Create a new stream for `target_addr` streaming the quotes of `tickers` and the order
+    /// books of `depth` according to `options`.
+    ///
+    /// Quotes go out as UDP datagrams unless `connection` is given, in which case they are
+    /// written as frames to that TCP command connection.
+    ///
+    /// The subscription is all-or-nothing: if any quote or depth ticker is rejected, no
+    /// stream is created.
+    /// The requested encoding must be one of those negotiated in the session.
+    ///
+    /// A client that subscribes again from the same address gets a new stream in place of
+    /// the previous one, which is closed first so that no quote is sent twice. A rejected
+    /// re-subscription leaves the previous stream running.
+    pub fn subscribe(
+        &mut self,
+        target_addr: SocketAddr,
+        tickers: &[Ticker],
+        depth: &[Ticker],
+        options: StreamOptions,
+        connection: Option<Arc<Mutex<TcpStream>>>,
+    ) -> Response {
+        let (accepted, mut rejected) = validate_tickers(&self.supported, tickers);
+        let (accepted_depth, rejected_depth) = validate_tickers(&self.supported, depth);
+        rejected.extend(rejected_depth);
+        if !rejected.is_empty() {
+            warn!("Rejected subscription from {}: {:?}", target_addr, rejected);
+            return response(accepted, rejected, None, None);
+        }
+        if !options.session.capabilities.encodings.contains(&options.encoding) {
+            warn!("Rejected subscription from {}: encoding {} not negotiated", target_addr, options.encoding);
+            return Response::failure(
+                SERVER_VERSION,
+                format!("encoding {} was not negotiated for this session", options.encoding),
+            );
+        }
+
+        if let Some(previous) = self.active_streams.get(&target_addr) {
+            info!(
+                "Client {} re-subscribed; replacing subscription #{} ({:?}) with {:?}",
+                target_addr, previous.subscription_id, previous.tickers, accepted
+            );
+            self.close(target_addr);
+        }
+
+        let sink = match connection {
+            Some(stream) => match TcpWriter::start(stream, target_addr) {
+                Ok(writer) => QuoteSink::Tcp(writer),
+                Err(e) => {
+                    error!("Failed to start the TCP writer of {}: {}", target_addr, e);
+                    return response(accepted, rejected, None, Some("could not start the TCP stream".to_string()));
+                }
+            },
+            None => QuoteSink::Udp(Arc::clone(&self.socket)),
+        };
+        let transport = match sink {
+            QuoteSink::Udp(_) => Transport::Udp,
+            QuoteSink::Tcp(_) => Transport::Tcp,
+        };
+        let subscriber_id = self.subscriptions.next_id();
+        let queue = Arc::new(SubscriberQueue::new(options.queue));
+        let subscriber = Subscriber::new(subscriber_id, Arc::clone(&queue), self.pool.ready_tx(subscriber_id));
+        let routed = self
+            .pool
+            .open(subscriber_id, ClientStream::new(sink, target_addr, &options), Arc::clone(&queue))
+            .and_then(|()| self.subscriptions.subscribe(subscriber, &accepted))
+            .and_then(|()| {
+                if accepted_depth.is_empty() {
+                    Ok(())
+                } else {
+                    self.subscriptions.add_depth(subscriber_id, &accepted_depth)
+                }
+            });
+        if let Err(e) = routed {
+            error!("Failed to subscribe client: {}", e);
+            self.pool.close(subscriber_id);
+            return response(accepted, rejected, None, Some("quote generator is not running".to_string()));
+        }
+
+        self.next_subscription_id += 1;
+        let subscription_id = self.next_subscription_id;
+        let stream = ActiveStream {
+            subscription_id,
+            tickers: accepted,
+            depth: accepted_depth,
+            options,
+            subscriber_id,
+            transport,
+            queue,
+            created_at: Instant::now(),
+        };
+        let response = stream.response(rejected, None);
+        self.active_streams.insert(target_addr, stream);
+        metrics().set_connected_clients(self.active_streams.len());
+
+        info!(
+            "A stream has been created for the client on a {} address: {}",
+            transport.to_string().to_uppercase(),
+            target_addr
+        );
+        if let Some(stream) = self.active_streams.get(&target_addr) {
+            info!(
+                "Stream {} uses protocol v{} with {} encoding",
+                target_addr,
+                stream.options.session.version,
+                stream.options.encoding
+            );
+            if let Some(interval) = stream.options.conflation {
+                info!("Stream {} conflates quotes every {:?}", target_addr, interval);
+            }
+            if !stream.depth.is_empty() {
+                info!("Stream {} follows the order books of {:?}", target_addr, stream.depth);
+            }
+        }
+
+        response
+    }
+
+    /// Add `tickers` to the live stream for `target_addr`, or remove them if `add` is false.
+    ///
+    /// Additions are all-or-nothing like subscriptions. Removing every ticker, when no depth
+    /// is streamed either, tears the stream down as if the client had unsubscribed.
+    pub fn update_tickers(&mut self, target_addr: SocketAddr, tickers: &[Ticker], add: bool) -> Response {
+        let Some(stream) = self.active_streams.get_mut(&target_addr) else {
+            return no_subscription(target_addr);
+        };
+
+        let routed = if add {
+            let (accepted, rejected) = validate_tickers(&self.supported, tickers);
+            if !rejected.is_empty() {
+                warn!("Rejected ADD_TICKERS from {}: {:?}", target_addr, rejected);
+                return stream.response(rejected, None);
+            }
+            let added: Vec<Ticker> = accepted
+                .into_iter()
+                .filter(|t| !stream.tickers.contains(t))
+                .collect();
+            stream.tickers.extend(added.iter().cloned());
+            info!("Stream {}: added {:?}", target_addr, added);
+            self.subscriptions.add_tickers(stream.subscriber_id, &added)
+        } else {
+            stream.tickers.retain(|t| !tickers.contains(t));
+            if stream.is_empty() {
+                return self.unsubscribe(target_addr);
+            }
+            info!("Stream {}: removed {:?}", target_addr, tickers);
+            self.subscriptions.remove_tickers(stream.subscriber_id, tickers)
+        };
+
+        if let Err(e) = routed {
+            error!("Failed to update tickers for {}: {}", target_addr, e);
+            return stream.response(Vec::new(), Some("quote generator is not running".to_string()));
+        }
+        stream.response(Vec::new(), None)
+    }
+
+    /// Add the order books of `tickers` to the live stream for `target_addr`, or remove
+    /// them if `add` is false.
+    ///
+    /// Behaves like `update_tickers`. Each added book starts with a snapshot.
+    pub fn update_depth(&mut self, target_addr: SocketAddr, tickers: &[Ticker], add: bool) -> Response {
+        let Some(stream) = self.active_streams.get_mut(&target_addr) else {
+            return no_subscription(target_addr);
+        };
+
+        let routed = if add {
+            let (accepted, rejected) = validate_tickers(&self.supported, tickers);
+            if !rejected.is_empty() {
+                warn!("Rejected ADD_DEPTH from {}: {:?}", target_addr, rejected);
+                return stream.response(rejected, None);
+            }
+            let added: Vec<Ticker> = accepted
+                .into_iter()
+                .filter(|t| !stream.depth.contains(t))
+                .collect();
+            stream.depth.extend(added.iter().cloned());
+            info!("Stream {}: added depth of {:?}", target_addr, added);
+            self.subscriptions.add_depth(stream.subscriber_id, &added)
+        } else {
+            stream.depth.retain(|t| !tickers.contains(t));
+            if stream.is_empty() {
+                return self.unsubscribe(target_addr);
+            }
+            info!("Stream {}: removed depth of {:?}", target_addr, tickers);
+            self.subscriptions.remove_depth(stream.subscriber_id, tickers)
+        };
+
+        if let Err(e) = routed {
+            error!("Failed to update depth for {}: {}", target_addr, e);
+            return stream.response(Vec::new(), Some("quote generator is not running".to_string()));
+        }
+        stream.response(Vec::new(), None)
+    }
+
+    /// Describe the live stream for `target_addr` without changing it.
+    pub fn status(&self, target_addr: SocketAddr) -> Response {
+        match self.active_streams.get(&target_addr) {
+            Some(stream) => stream.response(Vec::new(), None),
+            None => no_subscription(target_addr),
+        }
+    }
+
+    /// Transport of the live stream for `target_addr`, if there is one.
+    pub fn transport(&self, target_addr: SocketAddr) -> Option<Transport> {
+        self.active_streams.get(&target_addr).map(|stream| stream.transport)
+    }
+
+    /// Describe every live stream, oldest first. `last_ping` tells when a client last pinged.
+    pub fn clients(&self, last_ping: impl Fn(&SocketAddr) -> Option<Instant>) -> Vec<ClientInfo> {
+        let mut clients: Vec<ClientInfo> = self
+            .active_streams
+            .iter()
+            .map(|(addr, stream)| ClientInfo {
+                address: *addr,
+                transport: stream.transport,
+                subscription_id: stream.subscription_id,
+                tickers: stream.tickers.clone(),
+                depth: stream.depth.clone(),
+                age_ms: stream.created_at.elapsed().as_millis() as u64,
+                last_ping_ms: last_ping(addr).map(|at| at.elapsed().as_millis() as u64),
+                dropped_quotes: stream.queue.dropped(),
+            })
+            .collect();
+        clients.sort_by_key(|client| client.subscription_id);
+        clients
+    }
+
+    /// Tear down the stream for `target_addr` at the client's request.
+    pub fn unsubscribe(&mut self, target_addr: SocketAddr) -> Response {
+        match self.close(target_addr) {
+            Some(subscription_id) => {
+                info!("Stream for {} closed by client request", target_addr);
+                response(Vec::new(), Vec::new(), Some(subscription_id), None)
+            }
+            None => no_subscription(target_addr),
+        }
+    }
+
+    /// Stop the stream workers after the generator broadcast `QuoteEvent::Shutdown`, once
+    /// they have delivered it to every client.
+    pub fn shutdown(self) {
+        info!("Stopping {} client streams", self.active_streams.len());
+        self.pool.shutdown();
+    }
+
+    /// Forget the stream of generator subscriber `id` after the pool dropped it on its own,
+    /// returning the client's address if it was still registered.
+    pub fn forget(&mut self, id: SubscriberId) -> Option<SocketAddr> {
+        let target_addr = *self
+            .active_streams
+            .iter()
+            .find(|(_, stream)| stream.subscriber_id == id)?
+            .0;
+        self.close(target_addr);
+        Some(target_addr)
+    }
+
+    /// End the stream for `target_addr` at an operator's request, sending the client a
+    /// going-away notice with `reason`. Returns the subscription id if the stream existed.
+    pub fn kick(&mut self, target_addr: SocketAddr, reason: &str) -> Option<u64> {
+        let stream = self.remove(target_addr)?;
+        let _ = self.subscriptions.unsubscribe(stream.subscriber_id);
+        self.pool.kick(stream.subscriber_id, reason);
+        Some(stream.subscription_id)
+    }
+
+    /// Stop the stream for `target_addr`, returning its subscription id if it existed.
+    pub fn close(&mut self, target_addr: SocketAddr) -> Option<u64> {
+        let stream = self.remove(target_addr)?;
+        let _ = self.subscriptions.unsubscribe(stream.subscriber_id);
+        self.pool.close(stream.subscriber_id);
+        Some(stream.subscription_id)
+    }
+
+    /// Forget the stream for `target_addr` and stop watching the client's pings.
+    fn remove(&mut self, target_addr: SocketAddr) -> Option<ActiveStream> {
+        let stream = self.active_streams.remove(&target_addr)?;
+        metrics().set_connected_clients(self.active_streams.len());
+        if let Ok(mut monitor) = self.ping_monitor.lock() {
+            monitor.remove(&target_addr);
+        }
+        Some(stream)
+    }
+}
+
+/// Split requested tickers into those the generator can stream and those it cannot.
+fn validate_tickers(supported: &[Ticker], requested: &[Ticker]) -> (Vec<Ticker>, Vec<RejectedTicker>) {
+    let mut accepted = Vec::new();
+    let mut rejected = Vec::new();
+
+    for ticker in requested {
+        if *ticker == Ticker::UNKNOWN {
+            rejected.push(RejectedTicker { ticker: ticker.clone(), reason: RejectReason::UnknownTicker });
+        } else if !supported.contains(ticker) {
+            rejected.push(RejectedTicker { ticker: ticker.clone(), reason: RejectReason::NotGenerated });
+        } else if !accepted.contains(ticker) {
+            accepted.push(ticker.clone());
+        }
+    }
+    (accepted, rejected)
+}
+
+fn response(
+    accepted: Vec<Ticker>,
+    rejected: Vec<RejectedTicker>,
+    subscription_id: Option<u64>,
+    error: Option<String>,
+) -> Response {
+    Response {
+        accepted,
+        depth: Vec::new(),
+        rejected,
+        subscription_id,
+        server_version: SERVER_VERSION.to_string(),
+        error,
+        session: None,
+        quotes: Vec::new(),
+        ping: None,
+    }
+}
+
+fn no_subscription(target_addr: SocketAddr) -> Response {
+    Response::failure(SERVER_VERSION, format!("no active subscription for {}", target_addr))
+}
@@ -0,0 +1,220 @@
+//! Bounded per-subscriber event queues and the slow-consumer policy.
+//!
+//! The generator never writes to a client directly: it pushes each routed `QuoteEvent`
+//! into the subscriber's `SubscriberQueue` and wakes the consumer (a stream worker or the
+//! history recorder) by sending the subscriber id on its ready channel. A queue holds at
+//! most `capacity` quotes, so a stuck client costs a fixed amount of memory. When a quote
+//! arrives at a full queue, the configured `OverflowPolicy` decides what happens:
+//!
+//! - `drop-oldest` — the oldest queued quote is discarded;
+//! - `drop-newest` — the arriving quote is discarded;
+//! - `conflate` — a queued quote of the same ticker is replaced by the new one, so the
+//!   client still gets the latest price of every ticker (the oldest quote is discarded if
+//!   none of the same ticker is queued);
+//! - `disconnect` — the subscriber is dropped.
+//!
+//! Discarded quotes are counted per queue and in the server metrics, as are the quotes
+//! waiting in all queues; discarded quotes show up on the client as sequence gaps.
+//! Book messages of depth subscriptions count as quotes here, except that `conflate`
+//! discards the oldest event for them. A discarded book update shows up as a book sequence
+//! gap, which the client repairs from the next snapshot.
+//! `QuoteEvent::Shutdown` is always queued, regardless of the capacity.
+
+use crate::metrics::metrics;
+use crate::model::quote_generator::{QuoteEvent, SubscriberId};
+use clap::ValueEnum;
+use crossbeam_channel::Sender;
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use strum_macros::Display;
+
+/// What to do with a quote that arrives at a full queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, ValueEnum, Display)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum OverflowPolicy {
+    /// Discard the oldest queued quote to make room.
+    #[default]
+    DropOldest,
+    /// Discard the arriving quote.
+    DropNewest,
+    /// Keep only the latest queued quote per ticker.
+    Conflate,
+    /// Drop the subscriber.
+    Disconnect,
+}
+
+/// Size and overflow behavior of a subscriber queue.
+#[derive(Debug, Clone, Copy)]
+pub struct QueueConfig {
+    /// Most quotes held for the subscriber at once.
+    pub capacity: usize,
+    /// Behavior when a quote arrives at a full queue.
+    pub policy: OverflowPolicy,
+}
+
+/// Outcome of delivering an event to a subscriber.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// The event was queued (possibly in place of an older quote).
+    Queued,
+    /// The queue was full and the arriving quote was discarded.
+    Dropped,
+    /// The queue overflowed under `OverflowPolicy::Disconnect`; the subscriber must be
+    /// removed.
+    Disconnected,
+    /// The consumer no longer exists.
+    Gone,
+}
+
+/// Events taken from a queue by its consumer.
+pub struct Drained {
+    /// Queued events, oldest first.
+    pub events: Vec<QuoteEvent>,
+    /// The queue overflowed under `OverflowPolicy::Disconnect`.
+    pub disconnected: bool,
+}
+
+#[derive(Default)]
+struct QueueState {
+    events: VecDeque<QuoteEvent>,
+    /// The consumer has been woken and has not drained the queue yet.
+    scheduled: bool,
+    disconnected: bool,
+}
+
+/// Bounded queue between the generator and one subscriber.
+pub struct SubscriberQueue {
+    config: QueueConfig,
+    state: Mutex<QueueState>,
+    dropped: AtomicU64,
+}
+
+impl SubscriberQueue {
+    /// Create an empty queue.
+    pub fn new(config: QueueConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(QueueState::default()),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Size and overflow behavior of the queue.
+    pub fn config(&self) -> QueueConfig {
+        self.config
+    }
+
+    /// Quotes discarded by the overflow policy so far.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Take every queued event. The next push wakes the consumer again.
+    pub fn drain(&self) -> Drained {
+        let mut state = self.lock();
+        state.scheduled = false;
+        metrics().queued_quotes(-(state.events.len() as i64));
+        Drained {
+            events: state.events.drain(..).collect(),
+            disconnected: state.disconnected,
+        }
+    }
+
+    /// Queue `event`, applying the overflow policy. Also returns whether the consumer
+    /// must be woken.
+    fn push(&self, event: QuoteEvent) -> (Delivery, bool) {
+        let mut state = self.lock();
+        if state.disconnected {
+            return (Delivery::Disconnected, false);
+        }
+
+        let queued = state.events.len();
+        let delivery = match &event {
+            QuoteEvent::Quote(_) | QuoteEvent::Book(_) if queued >= self.config.capacity => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                metrics().dropped_quote();
+                match self.config.policy {
+                    OverflowPolicy::DropOldest => {
+                        state.events.pop_front();
+                        Delivery::Queued
+                    }
+                    OverflowPolicy::DropNewest => return (Delivery::Dropped, false),
+                    OverflowPolicy::Conflate => {
+                        let same_ticker = match &event {
+                            QuoteEvent::Quote(quote) => state
+                                .events
+                                .iter()
+                                .position(|queued| matches!(queued, QuoteEvent::Quote(q) if q.ticker == quote.ticker)),
+                            _ => None,
+                        };
+                        match same_ticker {
+                            Some(index) => state.events.remove(index),
+                            None => state.events.pop_front(),
+                        };
+                        Delivery::Queued
+                    }
+                    OverflowPolicy::Disconnect => {
+                        state.events.clear();
+                        state.disconnected = true;
+                        Delivery::Disconnected
+                    }
+                }
+            }
+            _ => Delivery::Queued,
+        };
+        if delivery == Delivery::Queued {
+            state.events.push_back(event);
+        }
+        metrics().queued_quotes(state.events.len() as i64 - queued as i64);
+
+        let wake = !state.scheduled;
+        state.scheduled = true;
+        (delivery, wake)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, QueueState> {
+        // The state is consistent after every statement, so a poisoned lock is still usable.
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Drop for SubscriberQueue {
+    fn drop(&mut self) {
+        let state = self.state.get_mut().unwrap_or_else(PoisonError::into_inner);
+        metrics().queued_quotes(-(state.events.len() as i64));
+    }
+}
+
+/// Generator-side handle to a subscriber: its queue and the channel that wakes its
+/// consumer.
+#[derive(Clone)]
+pub struct Subscriber {
+    id: SubscriberId,
+    queue: Arc<SubscriberQueue>,
+    ready_tx: Sender<SubscriberId>,
+}
+
+impl Subscriber {
+    /// Handle for subscriber `id` whose consumer drains `queue` when `id` arrives on
+    /// `ready_tx`.
+    pub fn new(id: SubscriberId, queue: Arc<SubscriberQueue>, ready_tx: Sender<SubscriberId>) -> Self {
+        Self { id, queue, ready_tx }
+    }
+
+    /// Identifier of the subscriber.
+    pub fn id(&self) -> SubscriberId {
+        self.id
+    }
+
+    /// Queue `event` and wake the consumer if it is not already scheduled.
+    pub fn deliver(&self, event: QuoteEvent) -> Delivery {
+        let (delivery, wake) = self.queue.push(event);
+        if wake && self.ready_tx.send(self.id).is_err() {
+            return Delivery::Gone;
+        }
+        delivery
+    }
+}
@@ -1,15 +1,41 @@
+use quote_server::metrics::{INVALID_VERB, metrics};
+use quote_server::model::stream_registry::SERVER_VERSION;
 use quote_common::ParserError;
-use quote_common::command::Command;
-use crossbeam_channel::Sender;
-use log::{error, info};
-use std::io::Read;
-use std::net::{SocketAddr, TcpListener};
+use quote_common::command::{Request, Response, Transport};
+use crossbeam_channel::{Sender, bounded};
+use log::{error, info, warn};
+use quote_common::framing::{read_frame, write_message};
+use quote_common::handshake::Session;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// How long a connection waits for the main loop to answer a command.
+pub(crate) const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Longest a write to a stalled client may block before the connection is given up.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A decoded client request forwarded to the main loop.
+pub struct IncomingCommand {
+    /// The request as sent by the client.
+    pub request: Request,
+    /// Address identifying the client's stream: for UDP, the TCP peer IP with the request's
+    /// port; for TCP, the peer address of the command connection.
+    pub target_addr: SocketAddr,
+    /// Protocol session negotiated on the connection (legacy if the client sent no `HELLO`).
+    pub session: Session,
+    /// Write half of the command connection, used to stream quotes for `tcp` subscriptions.
+    pub connection: Arc<Mutex<TcpStream>>,
+    /// Channel on which the main loop sends the reply for the client.
+    pub reply_tx: Sender<Response>,
+}
 
 /// TCP command receiver that accepts client subscription requests over TCP.
 ///
-/// Creates a listening socket and parses incoming `Command` messages from clients.
-/// For each successfully decoded command, the receiver emits the command together
-/// with the target client's UDP `SocketAddr` into a provided channel.
+/// Creates a listening socket and parses incoming `Request` messages from clients.
+/// For each successfully decoded command, the receiver emits an `IncomingCommand`
+/// into a provided channel and writes the main loop's `Response` back to the client.
 pub struct QuoteReceiver {
     /// The underlying TCP listening socket.
     pub(crate) socket: TcpListener,
@@ -17,16 +43,17 @@ pub struct QuoteReceiver {
 
 impl QuoteReceiver {
     /// Bind a new TCP receiver to the provided `bind_addr` (e.g., `0.0.0.0:8080`).
-    pub fn new(bind_addr: &str) -> Result<Self, ParserError> {
+    pub fn new(bind_addr: SocketAddr) -> Result<Self, ParserError> {
         let socket = TcpListener::bind(bind_addr)?;
         Ok(Self { socket })
     }
 
-    /// Blocking loop that accepts TCP connections, reads a single `Command` per
-    /// connection, and forwards it to `tx` with a computed UDP target address.
+    /// Blocking loop that accepts TCP connections and serves each of them on its own
+    /// thread. A connection may carry any number of framed `Request` messages; every
+    /// decoded request is forwarded to `tx` and answered with a framed `Response`.
     pub(crate) fn receive_loop_with_channel(
         self,
-        tx: Sender<(Command, SocketAddr)>,
+        tx: Sender<IncomingCommand>,
     ) -> Result<(), Box<dyn std::error::Error>> {
         info!(
             "Command TCP server is started on {}",
@@ -35,31 +62,138 @@ impl QuoteReceiver {
 
         for stream in self.socket.incoming() {
             match stream {
-                Ok(mut stream) => {
-                    let client_tcp_addr = stream.peer_addr()?;
-                    let mut buf = [0u8; 1024];
+                Ok(stream) => {
+                    let tx = tx.clone();
+                    thread::spawn(move || {
+                        let client_tcp_addr = match stream.peer_addr() {
+                            Ok(addr) => addr,
+                            Err(e) => {
+                                error!("Failed to resolve peer address: {}", e);
+                                return;
+                            }
+                        };
+                        if let Err(e) = Self::serve_connection(stream, client_tcp_addr, tx) {
+                            error!("Failed to process client command from {:?}: {}", client_tcp_addr, e);
+                        }
+                    });
+                }
+                Err(e) => error!("TCP connection error: {}", e),
+            }
+        }
+        Ok(())
+    }
 
-                    if let Err(e) = (|| -> Result<(), Box<dyn std::error::Error>> {
-                        let size = stream.read(&mut buf)?;
-                        let cmd: Command = serde_json::from_slice(&buf[..size])
-                            .map_err(|e| format!("JSON error: {}", e))?;
+    /// Read framed requests from a single connection until the peer disconnects.
+    ///
+    /// `HELLO` is answered here and fixes the session for all later requests on the
+    /// connection. Requests with an unknown verb or a malformed payload are answered with
+    /// a failed `Response`; the connection stays open because framing keeps the stream in
+    /// sync.
+    ///
+    /// All writes go through a shared writer so that quotes of a `tcp` subscription can be
+    /// streamed on the same connection. When the connection closes, such a subscription is
+    /// unsubscribed.
+    fn serve_connection(
+        stream: TcpStream,
+        client_tcp_addr: SocketAddr,
+        tx: Sender<IncomingCommand>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        let connection = Arc::new(Mutex::new(stream.try_clone()?));
+        let mut tcp_subscription = None;
+        metrics().command_connection(true);
+        let result = Self::serve_requests(stream, client_tcp_addr, &connection, &tx, &mut tcp_subscription);
+        metrics().command_connection(false);
 
-                        info!("Received command {:?}", cmd);
+        if let Some(request) = tcp_subscription {
+            info!("Command connection from {} closed; ending its TCP stream", client_tcp_addr);
+            let (reply_tx, _reply_rx) = bounded::<Response>(1);
+            let unsubscribe = IncomingCommand {
+                request,
+                target_addr: client_tcp_addr,
+                session: Session::legacy(),
+                connection,
+                reply_tx,
+            };
+            if tx.send(unsubscribe).is_err() {
+                info!("Server is shutting down; TCP stream of {} ends with it", client_tcp_addr);
+            }
+        }
+        result
+    }
 
-                        let port: u16 = cmd.port.parse()
-                            .map_err(|e| format!("Invalid UDP port in command: {}", e))?;
+    /// Request loop of `serve_connection`.
+    ///
+    /// When a `tcp` subscription is accepted, the `UNSUBSCRIBE` that ends it is stored in
+    /// `tcp_subscription`.
+    fn serve_requests(
+        mut stream: TcpStream,
+        client_tcp_addr: SocketAddr,
+        connection: &Arc<Mutex<TcpStream>>,
+        tx: &Sender<IncomingCommand>,
+        tcp_subscription: &mut Option<Request>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let reply = |response: &Response| -> Result<(), ParserError> {
+            write_message(&mut *connection.lock()?, response)
+        };
+        let mut session = Session::legacy();
+        while let Some(frame) = read_frame(&mut stream)? {
+            let request = match Request::from_slice(&frame) {
+                Ok(request) => request,
+                Err(e) => {
+                    metrics().command(INVALID_VERB);
+                    warn!("Rejected request from {}: {}", client_tcp_addr, e);
+                    reply(&Response::failure(SERVER_VERSION, e.to_string()))?;
+                    continue;
+                }
+            };
+            info!("Received command {:?}", request);
+            metrics().command(request.verb());
 
-                        let target_udp_addr = SocketAddr::new(client_tcp_addr.ip(), port);
-                        tx.send((cmd, target_udp_addr))?;
-                        Ok(())
-                    })() {
-                        error!("Failed to process client command from {:?}: {}", client_tcp_addr, e);
-                    }
+            let endpoint = match &request {
+                Request::Hello { version, capabilities } => {
+                    let response = match Session::negotiate(*version, capabilities) {
+                        Ok(negotiated) => {
+                            info!("Negotiated session with {}: {:?}", client_tcp_addr, negotiated);
+                            session = negotiated.clone();
+                            Response::hello(SERVER_VERSION, negotiated)
+                        }
+                        Err(e) => {
+                            warn!("Handshake with {} failed: {}", client_tcp_addr, e);
+                            Response::failure(SERVER_VERSION, e.to_string())
+                        }
+                    };
+                    reply(&response)?;
+                    continue;
                 }
-                Err(e) => error!("TCP connection error: {}", e),
+                other => *other.endpoint().ok_or("request has no endpoint")?,
+            };
+
+            let target_addr = match endpoint.transport {
+                Transport::Udp => SocketAddr::new(client_tcp_addr.ip(), endpoint.port),
+                Transport::Tcp => client_tcp_addr,
+            };
+            let over_tcp = endpoint.transport == Transport::Tcp;
+            let subscribes_over_tcp = over_tcp && matches!(request, Request::Subscribe { .. });
+            let unsubscribes_over_tcp = over_tcp && matches!(request, Request::Unsubscribe { .. });
+            let (reply_tx, reply_rx) = bounded::<Response>(1);
+            tx.send(IncomingCommand {
+                request,
+                target_addr,
+                session: session.clone(),
+                connection: Arc::clone(connection),
+                reply_tx,
+            })?;
+
+            let response = reply_rx.recv_timeout(REPLY_TIMEOUT)?;
+            if subscribes_over_tcp && response.is_success() {
+                *tcp_subscription = Some(Request::Unsubscribe { endpoint });
+            } else if unsubscribes_over_tcp && response.is_success() {
+                *tcp_subscription = None;
             }
+            reply(&response)?;
         }
+        info!("Command connection from {} closed", client_tcp_addr);
         Ok(())
     }
 }
-
@@ -1,4 +1,4 @@
-use crate::model::ping_monitor::PingMonitor;
+use quote_server::model::ping_monitor::PingMonitor;
 use log::{debug};
 use std::net::UdpSocket;
 use std::sync::{Arc, Mutex};
@@ -10,16 +10,20 @@ pub struct UdpPingListener;
 
 impl UdpPingListener {
     /// Spawn a background thread that reads UDP packets from `socket` and,
-    /// when a `PING` message is observed, updates `ping_monitor` for the sender.
+    /// when a `PING` message is observed, updates `ping_monitor` for the sender if it is a
+    /// registered subscriber.
     pub fn start(socket: Arc<UdpSocket>, ping_monitor: Arc<Mutex<PingMonitor>>) {
         thread::spawn(move || {
             let mut buf = [0u8; 128];
             loop {
-                if let Ok((size, addr)) = socket.recv_from(&mut buf) {
-                    if size >= 4 && &buf[..4] == b"PING" {
-                        debug!("Received ping from {}", addr);
-                        let mut monitor = ping_monitor.lock().unwrap();
-                        monitor.update_ping(addr);
+                if let Ok((size, addr)) = socket.recv_from(&mut buf)
+                    && size >= 4
+                    && &buf[..4] == b"PING"
+                {
+                    debug!("Received ping from {}", addr);
+                    let mut monitor = ping_monitor.lock().unwrap();
+                    if !monitor.update_ping(addr) {
+                        debug!("Ignoring ping from {}, which has no UDP stream", addr);
                     }
                 }
             }